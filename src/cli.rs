@@ -0,0 +1,880 @@
+use std::ffi::OsStr;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use iced::futures::executor::block_on;
+use serde_derive::Serialize;
+use crate::constants;
+use crate::conflicts::{Overlay, TreeNode};
+use crate::farc;
+use crate::installer;
+use crate::installer::ConflictAction;
+use crate::loader_conf::LoaderConfig;
+use crate::loader_installer;
+use crate::loader_update;
+use crate::manifest::{EntryStatus, Manifest};
+use crate::m4_settings::M4Settings;
+use crate::mod_catalog::{ModCatalog, ModEntry};
+use crate::mod_conf::ModConfig;
+use crate::profile::Profile;
+use crate::pv_db;
+use crate::util;
+
+//CLI, for using M4 without a window (scripts, SSH, Steam Deck game mode...)
+//Running m4 without a subcommand opens the GUI as usual.
+
+// println! to `out`, a failed write ends the command with an error.
+macro_rules! say {
+    ($out:expr, $($arg:tt)*) => {
+        writeln!($out, $($arg)*).map_err(|e| e.to_string())?
+    };
+}
+
+#[derive(Parser)]
+#[command(name = "m4", version, about = "The Mega Mix Mod Manager")]
+pub struct Cli {
+    /// Print machine readable JSON instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Use this game directory instead of the one from settings.toml
+    #[arg(long, global = true, value_name = "DIR")]
+    pub game_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum CliCommand {
+    /// List installed mods
+    List,
+    /// Enable a mod, by folder name or mod name
+    Enable { name: String },
+    /// Disable a mod, by folder name or mod name
+    Disable { name: String },
+    /// Install a mod from a zip, 7z or rar archive
+    Install {
+        archive: PathBuf,
+        /// What to do if the mod is already installed
+        #[arg(long, value_enum)]
+        on_conflict: Option<CliConflict>,
+    },
+    /// Show the load order, or move a mod in it
+    Priority {
+        #[command(subcommand)]
+        action: Option<PriorityAction>,
+    },
+    /// Show game files replaced by more than one enabled mod and which mod wins,
+    /// songs using the same ID and files inside archives that more than one mod has
+    Conflicts,
+    /// List the files inside a mod's .farc archives
+    Archives { name: String },
+    /// Show the game files replaced by enabled mods as a tree, with the mod each one comes from
+    Files {
+        /// Only show files more than one mod has
+        #[arg(long)]
+        conflicts_only: bool,
+    },
+    /// Write the installed mods, their versions, state and load order to a .toml or .json file
+    Export { file: PathBuf },
+    /// Compare a manifest with the installed mods, and apply it to the ones that are installed
+    Import {
+        file: PathBuf,
+        /// Only show what's different
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List, save, apply or delete profiles
+    Profile {
+        #[command(subcommand)]
+        action: Option<ProfileAction>,
+    },
+    /// Show whether DivaModLoader is installed and its version, or install it
+    Loader {
+        #[command(subcommand)]
+        action: Option<LoaderAction>,
+    },
+}
+
+impl CliCommand {
+    // Whether the command writes anything into the game directory.
+    fn writes_game_dir(&self) -> bool {
+        match self {
+            CliCommand::Enable { .. } | CliCommand::Disable { .. } | CliCommand::Install { .. } => true,
+            CliCommand::Priority { action } => action.is_some(),
+            CliCommand::Profile { action } => matches!(action, Some(ProfileAction::Apply { .. })),
+            CliCommand::Import { dry_run, .. } => !dry_run,
+            CliCommand::Loader { action } => matches!(action, Some(LoaderAction::Install { .. } | LoaderAction::Update { .. })),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum LoaderAction {
+    /// Install or update DivaModLoader from a release archive, the current config is kept
+    Install {
+        archive: PathBuf,
+        /// Version of the release, taken from the archive name if not given
+        #[arg(long)]
+        version: Option<String>,
+    },
+    /// Compare the installed version with the latest release
+    Check {
+        /// Release feed to use instead of the one in the settings (GitHub releases JSON, http or file URL)
+        #[arg(long)]
+        feed: Option<String>,
+    },
+    /// Download and install the latest release if it's newer than the installed one
+    Update {
+        /// Release feed to use instead of the one in the settings (GitHub releases JSON, http or file URL)
+        #[arg(long)]
+        feed: Option<String>,
+        /// Install the latest release even if it isn't newer
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileAction {
+    /// Save which mods are on and the load order as a profile
+    Save { name: String },
+    /// Turn mods on and off and set the load order from a profile
+    Apply { name: String },
+    /// Delete a profile
+    Delete { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum PriorityAction {
+    /// Move a mod to a position in the load order, 1 is the top
+    Move { name: String, position: usize },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CliConflict {
+    Overwrite,
+    KeepBoth,
+    Upgrade,
+}
+
+impl From<CliConflict> for ConflictAction {
+    fn from(c: CliConflict) -> Self {
+        match c {
+            CliConflict::Overwrite => ConflictAction::Overwrite,
+            CliConflict::KeepBoth => ConflictAction::KeepBoth,
+            CliConflict::Upgrade => ConflictAction::Upgrade,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ModInfo {
+    folder: String,
+    name: String,
+    author: Option<String>,
+    version: Option<String>,
+    description: Option<String>,
+    enabled: bool,
+    // 1 based position in the load order
+    priority: usize,
+}
+
+#[derive(Serialize)]
+struct ModErrorInfo {
+    folder: String,
+    error: String,
+}
+
+#[derive(Serialize)]
+struct ListOutput {
+    loader_enabled: bool,
+    mods_dir: PathBuf,
+    mods: Vec<ModInfo>,
+    errors: Vec<ModErrorInfo>,
+}
+
+#[derive(Serialize)]
+struct ToggleOutput {
+    folder: String,
+    enabled: bool,
+}
+
+#[derive(Serialize)]
+struct InstallOutput {
+    folder: String,
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct ProfileInfo {
+    name: String,
+    enabled: usize,
+    mods: usize,
+}
+
+#[derive(Serialize)]
+struct ApplyOutput {
+    profile: String,
+    changed: Vec<String>,
+    missing: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ImportEntryOutput {
+    folder: String,
+    name: String,
+    // installed, different_version or missing
+    status: &'static str,
+    version: Option<String>,
+    local_folder: Option<String>,
+    local_version: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ImportOutput {
+    mods: Vec<ImportEntryOutput>,
+    applied: bool,
+    changed: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ConflictOutput {
+    path: String,
+    winner: String,
+    losers: Vec<String>,
+    merged: bool,
+}
+
+#[derive(Serialize)]
+struct SongOutput {
+    folder: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct SongCollisionOutput {
+    id: u32,
+    songs: Vec<SongOutput>,
+}
+
+#[derive(Serialize)]
+struct ArchiveRefOutput {
+    folder: String,
+    archive: String,
+}
+
+#[derive(Serialize)]
+struct EntryOverlapOutput {
+    archives: Vec<ArchiveRefOutput>,
+    entries: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ConflictsOutput {
+    files: Vec<ConflictOutput>,
+    songs: Vec<SongCollisionOutput>,
+    archive_entries: Vec<EntryOverlapOutput>,
+}
+
+#[derive(Serialize)]
+struct ArchiveEntryOutput {
+    name: String,
+    size: u32,
+    original_size: u32,
+}
+
+#[derive(Serialize)]
+struct ArchiveOutput {
+    path: String,
+    entries: Vec<ArchiveEntryOutput>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct LoaderOutput {
+    installed: bool,
+    version: Option<String>,
+    enabled: bool,
+    mods: String,
+    priority: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct LoaderInstallOutput {
+    version: Option<String>,
+    kept_config: bool,
+}
+
+#[derive(Serialize)]
+struct LoaderCheckOutput {
+    installed: Option<String>,
+    latest: String,
+    update_available: bool,
+}
+
+#[derive(Serialize)]
+struct LoaderUpdateOutput {
+    updated: bool,
+    version: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ErrorOutput {
+    error: String,
+}
+
+// Everything a command needs to know about the game install.
+struct Context {
+    game_dir: PathBuf,
+    loader: LoaderConfig,
+    catalog: ModCatalog,
+}
+
+impl Context {
+    fn load(game_dir: Option<PathBuf>) -> Result<Context, String> {
+        let game_dir = match game_dir {
+            Some(dir) => dir,
+            None => Self::game_dir_from_settings()?,
+        };
+        if let Err(problem) = util::check_megamix_dir(&game_dir) {
+            return Err(format!("{} is not a usable Mega Mix+ directory: {}. {}", game_dir.display(), problem, problem.suggestion()));
+        }
+
+        let loader = LoaderConfig::load(&game_dir).map_err(|e| e.to_string())?;
+        let catalog = ModCatalog::scan(&loader.mods_dir(&game_dir)).map_err(|e| e.to_string())?;
+        Ok(Context { game_dir, loader, catalog })
+    }
+
+    fn game_dir_from_settings() -> Result<PathBuf, String> {
+        let path = util::get_config_path().ok_or("Cannot find the settings file")?;
+        let settings = block_on(M4Settings::load(path))
+            .map_err(|e| format!("{}, run m4 once to set it up or pass --game-dir", e))?;
+        Ok(PathBuf::from(settings.game_dir))
+    }
+
+    // --feed, then the feed in the settings, then the official one.
+    fn loader_feed(feed: Option<String>) -> String {
+        if let Some(feed) = feed {
+            return feed;
+        }
+        util::get_config_path()
+            .and_then(|path| block_on(M4Settings::load(path)).ok())
+            .unwrap_or_default()
+            .loader_feed()
+            .to_string()
+    }
+
+    fn order(&self) -> Vec<String> {
+        let installed: Vec<String> = self.catalog.mods.iter().map(|m| m.folder_name()).collect();
+        self.loader.ordered_mods(&installed)
+    }
+
+    // Finds a mod by folder name first, then by the name in its config.
+    fn find(&self, name: &str) -> Result<&ModEntry, String> {
+        if let Some(entry) = self.catalog.find(name) {
+            return Ok(entry);
+        }
+
+        let matches: Vec<&ModEntry> = self.catalog.mods.iter()
+            .filter(|m| m.display_name().eq_ignore_ascii_case(name))
+            .collect();
+        match matches.len() {
+            0 => Err(format!("No mod named \"{}\"", name)),
+            1 => Ok(matches[0]),
+            _ => Err(format!("More than one mod is named \"{}\", use the folder name instead", name)),
+        }
+    }
+}
+
+// Whether any of `args` (without the program name) is a subcommand.
+// Tells a CLI call with bad arguments apart from a launcher passing something extra
+// to the GUI, e.g the %U of a desktop file.
+pub fn names_subcommand<S: AsRef<OsStr>>(args: impl IntoIterator<Item = S>) -> bool {
+    let command = Cli::command();
+    args.into_iter().any(|arg| command.find_subcommand(arg).is_some())
+}
+
+// Runs a CLI command, returns the process exit code.
+pub fn run(cli: Cli) -> i32 {
+    run_to(cli, &mut io::stdout())
+}
+
+// `run`, with the output going to `out` instead of stdout. Errors still go to stderr unless --json is on.
+pub fn run_to(cli: Cli, out: &mut dyn Write) -> i32 {
+    let json = cli.json;
+    let command = match cli.command {
+        Some(command) => command,
+        None => return 0,
+    };
+
+    let res = Context::load(cli.game_dir).and_then(|ctx| {
+        //Reading works fine from a read only install, only commands that change something need to write
+        if command.writes_game_dir() {
+            util::check_mods_writable(&ctx.game_dir)
+                .map_err(|problem| format!("{}. {}", problem, problem.suggestion()))?;
+        }
+        match command {
+            CliCommand::List => list(&ctx, out, json),
+            CliCommand::Enable { name } => set_enabled(&ctx, out, &name, true, json),
+            CliCommand::Disable { name } => set_enabled(&ctx, out, &name, false, json),
+            CliCommand::Install { archive, on_conflict } => install(&ctx, out, &archive, on_conflict, json),
+            CliCommand::Priority { action: None } => show_priority(&ctx, out, json),
+            CliCommand::Priority { action: Some(PriorityAction::Move { name, position }) } => {
+                move_priority(&ctx, out, &name, position, json)
+            }
+            CliCommand::Profile { action } => profile(&ctx, out, action, json),
+            CliCommand::Loader { action } => loader(&ctx, out, action, json),
+            CliCommand::Conflicts => conflicts(&ctx, out, json),
+            CliCommand::Archives { name } => archives(&ctx, out, &name, json),
+            CliCommand::Files { conflicts_only } => files(&ctx, out, conflicts_only, json),
+            CliCommand::Export { file } => export(&ctx, out, &file, json),
+            CliCommand::Import { file, dry_run } => import(&ctx, out, &file, dry_run, json),
+        }
+    });
+
+    match res {
+        Ok(()) => 0,
+        Err(err) => {
+            if json {
+                let _ = print_json(out, &ErrorOutput { error: err });
+            } else {
+                eprintln!("error: {}", err);
+            }
+            1
+        }
+    }
+}
+
+fn loader(ctx: &Context, out: &mut dyn Write, action: Option<LoaderAction>, json: bool) -> Result<(), String> {
+    match action {
+        None => {
+            let installed = util::has_mod_loader(&ctx.game_dir);
+            let version = loader_installer::installed_version(&ctx.game_dir);
+            if json {
+                print_json(out, &LoaderOutput {
+                    installed,
+                    version,
+                    enabled: ctx.loader.enabled,
+                    mods: ctx.loader.mods.clone(),
+                    priority: ctx.loader.priority.clone(),
+                })?;
+                return Ok(());
+            }
+            if !installed {
+                say!(out, "DivaModLoader is not installed, use m4 loader install <archive>");
+                return Ok(());
+            }
+            say!(out, "DivaModLoader {}", version.as_deref().unwrap_or("(unknown version)"));
+            say!(out, "  enabled: {}", ctx.loader.enabled);
+            say!(out, "  mods folder: {}", ctx.loader.mods);
+            say!(out, "  priority: {} mod(s)", ctx.loader.priority.len());
+        }
+        Some(LoaderAction::Install { archive, version }) => {
+            let installed = loader_installer::install_loader(&archive, &ctx.game_dir, version)
+                .map_err(|e| e.to_string())?;
+            if json {
+                print_json(out, &LoaderInstallOutput { version: installed.version, kept_config: installed.kept_config })?;
+            } else {
+                say!(out, "Installed DivaModLoader {}", installed.version.as_deref().unwrap_or("(unknown version)"));
+                if installed.kept_config {
+                    say!(out, "Kept the existing {}", constants::LOADER_CONFIG_FILE);
+                }
+            }
+        }
+        Some(LoaderAction::Check { feed }) => {
+            let check = loader_update::check(&ctx.game_dir, &Context::loader_feed(feed)).map_err(|e| e.to_string())?;
+            if json {
+                print_json(out, &LoaderCheckOutput {
+                    update_available: check.update_available(),
+                    installed: check.installed,
+                    latest: check.latest.version,
+                })?;
+                return Ok(());
+            }
+            say!(out, "Installed: {}", check.installed.as_deref().unwrap_or("unknown"));
+            say!(out, "Latest: {}", check.latest.version);
+            if check.update_available() {
+                say!(out, "Run m4 loader update to install {}", check.latest.version);
+            }
+        }
+        Some(LoaderAction::Update { feed, force }) => {
+            let check = loader_update::check(&ctx.game_dir, &Context::loader_feed(feed)).map_err(|e| e.to_string())?;
+            if !check.update_available() && !force {
+                if json {
+                    print_json(out, &LoaderUpdateOutput { updated: false, version: check.installed })?;
+                } else {
+                    say!(out, "DivaModLoader {} is up to date", check.latest.version);
+                }
+                return Ok(());
+            }
+            if !json {
+                say!(out, "Downloading {}...", check.latest.archive_name);
+            }
+            let installed = loader_update::update(&ctx.game_dir, &check.latest).map_err(|e| e.to_string())?;
+            if json {
+                print_json(out, &LoaderUpdateOutput { updated: true, version: installed.version })?;
+            } else {
+                say!(out, "Installed DivaModLoader {}", check.latest.version);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn profile(ctx: &Context, out: &mut dyn Write, action: Option<ProfileAction>, json: bool) -> Result<(), String> {
+    let dir = Profile::dir().ok_or("Cannot find the config directory")?;
+    let (profiles, errors) = Profile::list(&dir);
+    let find = |name: &str| profiles.iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("No profile named \"{}\"", name));
+
+    match action {
+        None => {
+            let infos: Vec<ProfileInfo> = profiles.iter().map(|p| ProfileInfo {
+                name: p.name.clone(),
+                enabled: p.mods.values().filter(|e| **e).count(),
+                mods: p.mods.len(),
+            }).collect();
+            if json {
+                print_json(out, &infos)?;
+                return Ok(());
+            }
+            for info in &infos {
+                say!(out, "{} ({} of {} mods on)", info.name, info.enabled, info.mods);
+            }
+            for err in &errors {
+                say!(out, "[!] {}", err);
+            }
+        }
+        Some(ProfileAction::Save { name }) => {
+            let path = Profile::capture(&name, &ctx.game_dir, &ctx.catalog)?.save(&dir)?;
+            if json {
+                print_json(out, &path)?;
+            } else {
+                say!(out, "Saved {} to {}", name.trim(), path.display());
+            }
+        }
+        Some(ProfileAction::Apply { name }) => {
+            let profile = find(&name)?;
+            let report = profile.apply(&ctx.game_dir, &ctx.catalog)?;
+            if json {
+                print_json(out, &ApplyOutput { profile: profile.name.clone(), changed: report.changed, missing: report.missing })?;
+                return Ok(());
+            }
+            say!(out, "Applied {}, {} mod(s) changed", profile.name, report.changed.len());
+            for folder in &report.missing {
+                say!(out, "Not installed anymore: {}", folder);
+            }
+        }
+        Some(ProfileAction::Delete { name }) => {
+            let profile = find(&name)?;
+            profile.delete()?;
+            if json {
+                print_json(out, &profile.name)?;
+            } else {
+                say!(out, "Deleted {}", profile.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn conflicts(ctx: &Context, out: &mut dyn Write, json: bool) -> Result<(), String> {
+    let overlay = Overlay::build(&ctx.order(), &ctx.catalog);
+    let songs = pv_db::collisions(&ctx.order(), &ctx.catalog);
+    let overlaps = farc::overlaps(&ctx.order(), &ctx.catalog);
+    if json {
+        let files = overlay.conflicts().map(|e| ConflictOutput {
+            path: e.path.clone(),
+            winner: e.winner().to_string(),
+            losers: e.losers().to_vec(),
+            merged: false,
+        }).collect();
+        let songs = songs.into_iter().map(|c| SongCollisionOutput {
+            id: c.id,
+            songs: c.songs.into_iter().map(|s| SongOutput { folder: s.folder, name: s.name }).collect(),
+        }).collect();
+        let archive_entries = overlaps.into_iter().map(|o| EntryOverlapOutput {
+            archives: o.archives.into_iter().map(|a| ArchiveRefOutput { folder: a.folder, archive: a.archive }).collect(),
+            entries: o.entries,
+        }).collect();
+        print_json(out, &ConflictsOutput { files, songs, archive_entries })?;
+        return Ok(());
+    }
+
+    let groups = overlay.conflict_groups();
+    if groups.is_empty() && songs.is_empty() && overlaps.is_empty() {
+        say!(out, "No conflicts between enabled mods");
+    }
+    for group in &groups {
+        say!(out, "{} wins over {} ({} file(s))", group.winner, group.losers.join(", "), group.paths.len());
+        for path in &group.paths {
+            say!(out, "  {}", path);
+        }
+    }
+    for collision in &songs {
+        let names: Vec<String> = collision.songs.iter().map(|s| format!("{} [{}]", s.name, s.folder)).collect();
+        say!(out, "Song ID pv_{:03} used by {}", collision.id, names.join(", "));
+    }
+    for overlap in &overlaps {
+        let archives: Vec<String> = overlap.archives.iter().map(|a| format!("{} [{}]", a.archive, a.folder)).collect();
+        say!(out, "Same files in {} ({} file(s))", archives.join(", "), overlap.entries.len());
+        for entry in &overlap.entries {
+            say!(out, "  {}", entry);
+        }
+    }
+    Ok(())
+}
+
+fn archives(ctx: &Context, out: &mut dyn Write, name: &str, json: bool) -> Result<(), String> {
+    let entry = ctx.find(name)?;
+    if json {
+        let archives: Vec<ArchiveOutput> = entry.archives.iter().map(|a| match &a.contents {
+            Ok(farc) => ArchiveOutput {
+                path: a.path.clone(),
+                entries: farc.entries.iter().map(|e| ArchiveEntryOutput {
+                    name: e.name.clone(),
+                    size: e.size,
+                    original_size: e.original_size,
+                }).collect(),
+                error: None,
+            },
+            Err(err) => ArchiveOutput { path: a.path.clone(), entries: Vec::new(), error: Some(err.to_string()) },
+        }).collect();
+        print_json(out, &archives)?;
+        return Ok(());
+    }
+
+    if entry.archives.is_empty() {
+        say!(out, "{} has no archives", entry.display_name());
+    }
+    for archive in &entry.archives {
+        match &archive.contents {
+            Ok(farc) => {
+                say!(out, "{} ({} file(s))", archive.path, farc.entries.len());
+                for file in &farc.entries {
+                    say!(out, "  {} ({} bytes)", file.name, file.original_size);
+                }
+            }
+            Err(err) => say!(out, "{}: {}", archive.path, err),
+        }
+    }
+    Ok(())
+}
+
+fn files(ctx: &Context, out: &mut dyn Write, conflicts_only: bool, json: bool) -> Result<(), String> {
+    let overlay = Overlay::build(&ctx.order(), &ctx.catalog);
+    if json {
+        let files: Vec<ConflictOutput> = overlay.entries.iter()
+            .filter(|e| !conflicts_only || e.is_conflict())
+            .map(|e| ConflictOutput {
+                path: e.path.clone(),
+                winner: e.winner().to_string(),
+                losers: e.losers().to_vec(),
+                merged: e.merged,
+            })
+            .collect();
+        print_json(out, &files)?;
+        return Ok(());
+    }
+
+    let tree = overlay.tree();
+    if tree.children.is_empty() {
+        say!(out, "No enabled mod replaces any game files");
+    }
+    print_tree(out, &tree, 0, conflicts_only)?;
+    Ok(())
+}
+
+fn print_tree(out: &mut dyn Write, node: &TreeNode, depth: usize, conflicts_only: bool) -> Result<(), String> {
+    for child in &node.children {
+        if conflicts_only && child.conflict_count() == 0 {
+            continue;
+        }
+        let indent = "  ".repeat(depth);
+        match &child.entry {
+            None => {
+                say!(out, "{}{}/", indent, child.name);
+                print_tree(out, child, depth + 1, conflicts_only)?;
+            }
+            Some(entry) if entry.is_conflict() => {
+                say!(out, "{}{} [{}, hides {}]", indent, child.name, entry.winner(), entry.losers().join(", "));
+            }
+            Some(entry) if entry.merged => {
+                say!(out, "{}{} [merged from {}]", indent, child.name, entry.providers.join(", "));
+            }
+            Some(entry) => say!(out, "{}{} [{}]", indent, child.name, entry.winner()),
+        }
+    }
+    Ok(())
+}
+
+fn export(ctx: &Context, out: &mut dyn Write, file: &Path, json: bool) -> Result<(), String> {
+    let manifest = Manifest::export(&ctx.loader, &ctx.catalog);
+    manifest.save(file)?;
+    if json {
+        print_json(out, &file)?;
+    } else {
+        say!(out, "Exported {} mods to {}", manifest.mods.len(), file.display());
+    }
+    Ok(())
+}
+
+fn import(ctx: &Context, out: &mut dyn Write, file: &Path, dry_run: bool, json: bool) -> Result<(), String> {
+    let diff = Manifest::load(file).map_err(|e| e.to_string())?.diff(&ctx.catalog);
+
+    let mut changed = Vec::new();
+    if !dry_run {
+        let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        changed = diff.to_profile(&name).apply(&ctx.game_dir, &ctx.catalog)?.changed;
+    }
+
+    if json {
+        let mods = diff.entries.iter().map(|e| ImportEntryOutput {
+            folder: e.entry.folder.clone(),
+            name: e.entry.display_name().to_string(),
+            status: match e.status {
+                EntryStatus::Installed => "installed",
+                EntryStatus::DifferentVersion(_) => "different_version",
+                EntryStatus::Missing => "missing",
+            },
+            version: e.entry.version.clone(),
+            local_folder: e.local_folder.clone(),
+            local_version: e.local_folder.as_ref()
+                .and_then(|f| ctx.catalog.find(f))
+                .and_then(|m| m.config.version.clone()),
+        }).collect();
+        print_json(out, &ImportOutput { mods, applied: !dry_run, changed })?;
+        return Ok(());
+    }
+
+    say!(out, "{}", diff);
+    if !dry_run {
+        say!(out, "Applied, {} mod(s) changed", changed.len());
+    }
+    Ok(())
+}
+
+fn print_json<T: serde::Serialize>(out: &mut dyn Write, value: &T) -> Result<(), String> {
+    say!(out, "{}", serde_json::to_string_pretty(value).expect("Failed to serialize output"));
+    Ok(())
+}
+
+fn list(ctx: &Context, out: &mut dyn Write, json: bool) -> Result<(), String> {
+    let order = ctx.order();
+    let mods: Vec<ModInfo> = ctx.catalog.mods.iter().map(|m| {
+        let folder = m.folder_name();
+        ModInfo {
+            priority: order.iter().position(|f| *f == folder).map(|i| i + 1).unwrap_or(0),
+            name: m.display_name(),
+            folder,
+            author: m.config.author.clone(),
+            version: m.config.version.clone(),
+            description: m.config.description.clone(),
+            enabled: m.config.enabled,
+        }
+    }).collect();
+    let errors: Vec<ModErrorInfo> = ctx.catalog.errors.iter().map(|e| ModErrorInfo {
+        folder: e.folder_name(),
+        error: e.error.to_string(),
+    }).collect();
+
+    if json {
+        print_json(out, &ListOutput {
+            loader_enabled: ctx.loader.enabled,
+            mods_dir: ctx.catalog.mods_dir.clone(),
+            mods,
+            errors,
+        })?;
+        return Ok(());
+    }
+
+    if !ctx.loader.enabled {
+        say!(out, "Note: the mod loader is disabled");
+    }
+    for m in &mods {
+        let mut line = format!("[{}] {}", if m.enabled { "x" } else { " " }, m.name);
+        if let Some(version) = &m.version {
+            line.push_str(&format!(" v{}", version));
+        }
+        if let Some(author) = &m.author {
+            line.push_str(&format!(" by {}", author));
+        }
+        if m.name != m.folder {
+            line.push_str(&format!(" ({})", m.folder));
+        }
+        say!(out, "{}", line);
+    }
+    for e in &errors {
+        say!(out, "[!] {}: {}", e.folder, e.error);
+    }
+    Ok(())
+}
+
+fn set_enabled(ctx: &Context, out: &mut dyn Write, name: &str, enabled: bool, json: bool) -> Result<(), String> {
+    let entry = ctx.find(name)?;
+    block_on(ModConfig::save_enabled(entry.config_path(), enabled))?;
+
+    let folder = entry.folder_name();
+    if json {
+        print_json(out, &ToggleOutput { folder, enabled })?;
+    } else {
+        say!(out, "{} {}", if enabled { "Enabled" } else { "Disabled" }, entry.display_name());
+    }
+    Ok(())
+}
+
+fn install(ctx: &Context, out: &mut dyn Write, archive: &Path, on_conflict: Option<CliConflict>, json: bool) -> Result<(), String> {
+    let installed = installer::install_archive(archive, &ctx.catalog.mods_dir, on_conflict.map(ConflictAction::from))
+        .map_err(|e| {
+            if let installer::InstallErrorType::AlreadyInstalled(_) = e.0 {
+                return format!("{}, pass --on-conflict to choose what to do", e);
+            }
+            e.to_string()
+        })?;
+
+    if json {
+        print_json(out, &InstallOutput { folder: installed.folder, path: installed.path })?;
+    } else {
+        say!(out, "Installed {} to {}", installed.folder, installed.path.display());
+    }
+    Ok(())
+}
+
+fn show_priority(ctx: &Context, out: &mut dyn Write, json: bool) -> Result<(), String> {
+    let order = ctx.order();
+    if json {
+        print_json(out, &order)?;
+        return Ok(());
+    }
+
+    for (i, folder) in order.iter().enumerate() {
+        say!(out, "{}. {}", i + 1, folder);
+    }
+    Ok(())
+}
+
+fn move_priority(ctx: &Context, out: &mut dyn Write, name: &str, position: usize, json: bool) -> Result<(), String> {
+    let folder = ctx.find(name)?.folder_name();
+    let mut order = ctx.order();
+    if position == 0 || position > order.len() {
+        return Err(format!("Position must be between 1 and {}", order.len()));
+    }
+
+    order.retain(|f| *f != folder);
+    order.insert(position - 1, folder);
+    block_on(LoaderConfig::save_priority(ctx.game_dir.clone(), ctx.loader.priority_for(&order)))?;
+
+    if json {
+        print_json(out, &order)?;
+    } else {
+        for (i, folder) in order.iter().enumerate() {
+            say!(out, "{}. {}", i + 1, folder);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use iced::{Alignment, Button, button, Color, Column, Element, Length, Row, Scrollable, scrollable, Text};
+use crate::conflicts::{ConflictGroup, Overlay};
+use crate::farc;
+use crate::mod_catalog::ModCatalog;
+use crate::pv_db;
+use crate::style::M4ButtonStyleSheet;
+
+//Conflicts tab on the home screen
+//One row per set of mods fighting over the same files, the files show when a row is opened.
+//Songs from different mods using the same PV ID are listed under them,
+//then files inside of archives that more than one mod has.
+
+#[derive(Debug, Clone)]
+pub enum ConflictMessage {
+    Toggle(usize),
+}
+
+struct ConflictRow {
+    group: ConflictGroup,
+    // Display names, in the same order as the folders in `group`
+    winner_name: String,
+    loser_names: Vec<String>,
+    expanded: bool,
+    toggle_btn: button::State,
+}
+
+#[derive(Default)]
+pub struct ConflictList {
+    rows: Vec<ConflictRow>,
+    // One line per PV ID used by more than one mod
+    songs: Vec<String>,
+    // Where the overlapping files are, and the files
+    entries: Vec<(String, String)>,
+    scroll: scrollable::State,
+}
+
+impl ConflictList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // `order` is the current load order, highest priority first.
+    pub fn set_mods(&mut self, order: &[String], catalog: &ModCatalog) {
+        let name = |folder: &str| catalog.find(folder).map(|m| m.display_name()).unwrap_or_else(|| folder.to_string());
+        self.rows = Overlay::build(order, catalog).conflict_groups()
+            .into_iter()
+            .map(|group| ConflictRow {
+                winner_name: name(&group.winner),
+                loser_names: group.losers.iter().map(|l| name(l)).collect(),
+                group,
+                expanded: false,
+                toggle_btn: button::State::new(),
+            })
+            .collect();
+        self.songs = pv_db::collisions(order, catalog)
+            .into_iter()
+            .map(|collision| {
+                let songs: Vec<String> = collision.songs.iter().map(|s| format!("{} ({})", s.name, name(&s.folder))).collect();
+                format!("pv_{:03}: {}", collision.id, songs.join(", "))
+            })
+            .collect();
+        self.entries = farc::overlaps(order, catalog)
+            .into_iter()
+            .map(|overlap| {
+                let archives: Vec<String> = overlap.archives.iter().map(|a| format!("{} ({})", a.archive, name(&a.folder))).collect();
+                (archives.join(", "), overlap.entries.join(", "))
+            })
+            .collect();
+    }
+
+    pub fn update(&mut self, message: ConflictMessage) {
+        match message {
+            ConflictMessage::Toggle(index) => {
+                if let Some(row) = self.rows.get_mut(index) {
+                    row.expanded = !row.expanded;
+                }
+            }
+        }
+    }
+
+    pub fn view(&mut self) -> Element<'_, ConflictMessage> {
+        let grey = Color::from_rgb(0.4, 0.4, 0.4);
+        let mut content = Column::new()
+            .spacing(10)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .push(
+                Text::new("Enabled mods replacing the same game files, the mod higher in the load order wins.")
+                    .size(16)
+                    .color(grey)
+            );
+
+        let mut list = Scrollable::new(&mut self.scroll)
+            .spacing(10)
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        if self.rows.is_empty() && self.songs.is_empty() && self.entries.is_empty() {
+            list = list.push(Text::new("No conflicts between enabled mods."));
+        }
+
+        for (i, row) in self.rows.iter_mut().enumerate() {
+            let count = row.group.paths.len();
+            let mut item = Column::new()
+                .spacing(3)
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Alignment::Center)
+                        .push(
+                            Column::new()
+                                .width(Length::Fill)
+                                .push(Text::new(format!("{} wins over {}", row.winner_name, row.loser_names.join(", "))).size(20))
+                                .push(Text::new(format!("{} file(s)", count)).size(16).color(grey))
+                        )
+                        .push(
+                            Button::new(&mut row.toggle_btn, Text::new(if row.expanded { "Hide files" } else { "Show files" }))
+                                .style(M4ButtonStyleSheet)
+                                .on_press(ConflictMessage::Toggle(i))
+                        )
+                );
+            if row.expanded {
+                for path in &row.group.paths {
+                    item = item.push(Text::new(path.as_str()).size(14));
+                }
+            }
+            list = list.push(item);
+        }
+
+        if !self.songs.is_empty() {
+            list = list.push(
+                Column::new()
+                    .push(Text::new("Song ID collisions").size(20))
+                    .push(Text::new("Songs from different mods using the same PV ID, they will break each other in game.").size(16).color(grey))
+            );
+        }
+        for line in &self.songs {
+            list = list.push(Text::new(line.as_str()).size(16));
+        }
+
+        if !self.entries.is_empty() {
+            list = list.push(
+                Column::new()
+                    .push(Text::new("Files inside archives").size(20))
+                    .push(Text::new("Archives from different mods with files of the same name, the game loads whichever it finds first.").size(16).color(grey))
+            );
+        }
+        for (archives, entries) in &self.entries {
+            list = list.push(
+                Column::new()
+                    .spacing(3)
+                    .push(Text::new(archives.as_str()).size(16))
+                    .push(Text::new(entries.as_str()).size(14).color(grey))
+            );
+        }
+
+        content = content.push(list);
+        content.into()
+    }
+}
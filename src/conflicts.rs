@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use crate::mod_catalog::ModCatalog;
+use crate::pv_db;
+
+//Conflicts, what happens when enabled mods replace the same game file
+//Every file goes through the load order, the first mod that has it wins,
+//the rest are never loaded. The same overlay also gives the merged file tree the game ends up seeing.
+//mod_pv_db.txt is merged instead of replaced, pv_db checks those for songs using the same ID.
+
+#[derive(Debug, Clone)]
+pub struct OverlayEntry {
+    // As written by the winning mod, e.g rom/2d/spr_gam_cmn.farc
+    pub path: String,
+    // Folder names of the mods that have the file, the winner first
+    pub providers: Vec<String>,
+    // mod_pv_db.txt, the loader merges every copy so nobody loses
+    pub merged: bool,
+}
+
+impl OverlayEntry {
+    pub fn winner(&self) -> &str {
+        &self.providers[0]
+    }
+
+    pub fn losers(&self) -> &[String] {
+        &self.providers[1..]
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        !self.merged && self.providers.len() > 1
+    }
+}
+
+// Every game file replaced by an enabled mod, the way DivaModLoader sees it.
+#[derive(Debug, Clone, Default)]
+pub struct Overlay {
+    // Sorted by path
+    pub entries: Vec<OverlayEntry>,
+}
+
+// A folder or file in the merged game tree, built from the overlay.
+#[derive(Debug, Clone, Default)]
+pub struct TreeNode {
+    pub name: String,
+    // Full path from the game root, e.g rom/2d
+    pub path: String,
+    // Folders first, then files, both sorted by name
+    pub children: Vec<TreeNode>,
+    // Set for files
+    pub entry: Option<OverlayEntry>,
+}
+
+impl TreeNode {
+    pub fn is_dir(&self) -> bool {
+        self.entry.is_none()
+    }
+
+    // Number of files under this node that more than one mod has.
+    pub fn conflict_count(&self) -> usize {
+        match &self.entry {
+            Some(entry) => entry.is_conflict() as usize,
+            None => self.children.iter().map(|c| c.conflict_count()).sum(),
+        }
+    }
+
+    pub fn file_count(&self) -> usize {
+        match &self.entry {
+            Some(_) => 1,
+            None => self.children.iter().map(|c| c.file_count()).sum(),
+        }
+    }
+
+    fn insert(&mut self, parts: &[&str], entry: &OverlayEntry) {
+        let (first, rest) = match parts.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let is_dir = !rest.is_empty();
+        let index = match self.children.iter().position(|c| c.name.eq_ignore_ascii_case(first) && c.is_dir() == is_dir) {
+            Some(index) => index,
+            None => {
+                let path = if self.path.is_empty() { first.to_string() } else { format!("{}/{}", self.path, first) };
+                self.children.push(TreeNode { name: first.to_string(), path, ..Default::default() });
+                self.children.len() - 1
+            }
+        };
+        if rest.is_empty() {
+            self.children[index].entry = Some(entry.clone());
+        } else {
+            self.children[index].insert(rest, entry);
+        }
+    }
+
+    fn sort(&mut self) {
+        self.children.sort_by(|a, b| {
+            b.is_dir().cmp(&a.is_dir()).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+        for child in &mut self.children {
+            child.sort();
+        }
+    }
+}
+
+// Files where the same mods are fighting, grouped so a big overlap shows as one line.
+#[derive(Debug, Clone)]
+pub struct ConflictGroup {
+    pub winner: String,
+    pub losers: Vec<String>,
+    pub paths: Vec<String>,
+}
+
+impl Overlay {
+    // `order` is the load order, highest priority first. Disabled mods are skipped.
+    pub fn build(order: &[String], catalog: &ModCatalog) -> Overlay {
+        let mut entries: BTreeMap<String, OverlayEntry> = BTreeMap::new();
+        for entry in order.iter().filter_map(|folder| catalog.find(folder)) {
+            if !entry.config.enabled {
+                continue;
+            }
+            let folder = entry.folder_name();
+            for file in &entry.files {
+                entries.entry(file.key())
+                    .or_insert_with(|| OverlayEntry {
+                        path: file.path.clone(),
+                        providers: Vec::new(),
+                        merged: pv_db::is_pv_db(file),
+                    })
+                    .providers
+                    .push(folder.clone());
+            }
+        }
+        Overlay { entries: entries.into_values().collect() }
+    }
+
+    // The overlay as a folder tree, the root node has no name.
+    pub fn tree(&self) -> TreeNode {
+        let mut root = TreeNode::default();
+        for entry in &self.entries {
+            let parts: Vec<&str> = entry.path.split('/').collect();
+            root.insert(&parts, entry);
+        }
+        root.sort();
+        root
+    }
+
+    pub fn conflicts(&self) -> impl Iterator<Item = &OverlayEntry> {
+        self.entries.iter().filter(|e| e.is_conflict())
+    }
+
+    pub fn conflict_groups(&self) -> Vec<ConflictGroup> {
+        let mut groups: Vec<ConflictGroup> = Vec::new();
+        for entry in self.conflicts() {
+            match groups.iter_mut().find(|g| g.winner == entry.winner() && g.losers == entry.losers()) {
+                Some(group) => group.paths.push(entry.path.clone()),
+                None => groups.push(ConflictGroup {
+                    winner: entry.winner().to_string(),
+                    losers: entry.losers().to_vec(),
+                    paths: vec![entry.path.clone()],
+                }),
+            }
+        }
+        groups
+    }
+}
@@ -1,5 +1,14 @@
 use iced::Color;
 
-pub(crate) const APPNAME: &'static str = "M4";
-pub(crate) const SETTINGS_FILE: &'static str = "settings.toml";
-pub(crate) const M4BLUE: &'static Color = &Color::from_rgb(0.0, 0.0, 1.0);
\ No newline at end of file
+pub(crate) const APPNAME: &str = "M4";
+pub(crate) const SETTINGS_FILE: &str = "settings.toml";
+pub(crate) const M4BLUE: &Color = &Color::from_rgb(0.0, 0.0, 1.0);
+pub(crate) const MEGAMIX_EXE: &str = "DivaMegaMix.exe";
+pub(crate) const MODS_DIR: &str = "mods";
+pub(crate) const MOD_CONFIG_FILE: &str = "config.toml";
+pub(crate) const LOADER_CONFIG_FILE: &str = "config.toml";
+pub(crate) const LOADER_DLL: &str = "dinput8.dll";
+pub(crate) const PROFILES_DIR: &str = "profiles";
+pub(crate) const STAGING_DIR: &str = ".m4-staging";
+pub(crate) const LOADER_RECORD_FILE: &str = "m4_loader.toml";
+pub(crate) const LOADER_RELEASE_FEED: &str = "https://api.github.com/repos/blueskythlikesclouds/DivaModLoader/releases";
@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use ashpd::desktop::open_uri::OpenURIProxy;
+use ashpd::{WindowIdentifier, zbus};
+use iced::{Alignment, Button, button, Column, Command, Element, Row, Text};
+use crate::m4_settings::LoadError;
+use crate::util;
+use crate::style::M4ButtonStyleSheet;
+
+//Error screen, shown when a file M4 needs can't be loaded.
+//Retrying and starting fresh are up to the parent, so those messages are handled in M4.
+
+#[derive(Debug, Clone)]
+pub enum ErrorMessage {
+    RetryClicked,
+    // The broken file is moved out of the way before the parent starts fresh, see `back_up_file`
+    StartFreshClicked,
+    // Where the broken file went, the parent starts fresh once it's Ok
+    FileBackedUp(Result<PathBuf, String>),
+    OpenFileClicked,
+    FileOpened(Result<(), String>),
+}
+
+#[derive(Default)]
+pub struct ErrorScreen {
+    error: Option<LoadError>,
+    // Anything that went wrong on the error screen itself, like the file not opening
+    status: Option<String>,
+    retry_btn: button::State,
+    fresh_btn: button::State,
+    open_btn: button::State,
+}
+
+impl ErrorScreen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_error(&mut self, error: LoadError) {
+        self.error = Some(error);
+        self.status = None;
+    }
+
+    pub fn error(&self) -> Option<&LoadError> {
+        self.error.as_ref()
+    }
+
+    // Moves the broken file to <name>.broken, so the next load doesn't trip over it.
+    // Done in the background, ends with FileBackedUp.
+    pub fn back_up_file(&self) -> Command<ErrorMessage> {
+        let path = match &self.error {
+            Some(err) => err.path().to_path_buf(),
+            None => return Command::none(),
+        };
+        Command::perform(util::run_blocking(move || move_aside(&path)), ErrorMessage::FileBackedUp)
+    }
+
+    pub fn update(&mut self, message: ErrorMessage) -> Command<ErrorMessage> {
+        match message {
+            ErrorMessage::OpenFileClicked => {
+                let path = match &self.error {
+                    Some(err) => err.path().to_path_buf(),
+                    None => return Command::none(),
+                };
+                Command::perform(open_file(path), ErrorMessage::FileOpened)
+            }
+            ErrorMessage::FileOpened(res) => {
+                self.status = res.err().map(|e| format!("Cannot open the file: {}", e));
+                Command::none()
+            }
+            ErrorMessage::FileBackedUp(Err(err)) => {
+                self.status = Some(err);
+                Command::none()
+            }
+            //The parent does these
+            ErrorMessage::RetryClicked | ErrorMessage::StartFreshClicked | ErrorMessage::FileBackedUp(Ok(_)) => Command::none(),
+        }
+    }
+
+    pub fn view(&mut self) -> Element<'_, ErrorMessage> {
+        let ErrorScreen { error, status, retry_btn, fresh_btn, open_btn } = self;
+
+        let mut content = Column::new()
+            .spacing(10)
+            .padding(20)
+            .align_items(Alignment::Center)
+            .push(Text::new("Something went wrong").size(30));
+
+        //Only files can be opened or backed up, not a whole mods folder
+        let mut is_file = false;
+        if let Some(err) = error {
+            content = content
+                .push(Text::new(err.kind().to_string()))
+                .push(Text::new(err.path().display().to_string()).size(16));
+            is_file = err.path().is_file();
+        }
+
+        let mut actions = Row::new()
+            .spacing(10)
+            .push(Button::new(retry_btn, Text::new("Retry"))
+                .style(M4ButtonStyleSheet)
+                .on_press(ErrorMessage::RetryClicked));
+        if is_file {
+            actions = actions
+                .push(Button::new(open_btn, Text::new("Open file"))
+                    .style(M4ButtonStyleSheet)
+                    .on_press(ErrorMessage::OpenFileClicked))
+                .push(Button::new(fresh_btn, Text::new("Back up and start fresh"))
+                    .style(M4ButtonStyleSheet)
+                    .on_press(ErrorMessage::StartFreshClicked));
+        }
+        content = content.push(actions);
+
+        if let Some(status) = status {
+            content = content.push(Text::new(status.as_str()).size(16));
+        }
+
+        content.into()
+    }
+}
+
+// Returns where the file went.
+fn move_aside(path: &Path) -> Result<PathBuf, String> {
+    if !path.is_file() {
+        return Err(String::from("There is no file to back up"));
+    }
+    let backup = broken_copy_path(path);
+    fs::rename(path, &backup).map_err(|e| format!("Cannot back up {}: {}", path.display(), e))?;
+    Ok(backup)
+}
+
+// settings.toml -> settings.toml.broken, or settings.toml.broken.2 if that's taken too.
+fn broken_copy_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let mut backup = path.with_file_name(format!("{}.broken", name));
+    let mut n = 2;
+    while backup.exists() {
+        backup = path.with_file_name(format!("{}.broken.{}", name, n));
+        n += 1;
+    }
+    backup
+}
+
+// Opens the file in whatever the user has set up for it, through the OpenURI portal.
+async fn open_file(path: PathBuf) -> Result<(), String> {
+    let file = fs::File::open(&path).map_err(|e| e.to_string())?;
+    let connection = zbus::Connection::session().await.map_err(|e| e.to_string())?;
+    let proxy = OpenURIProxy::new(&connection).await.map_err(|e| e.to_string())?;
+    proxy.open_file(&WindowIdentifier::None, &file, true, true).await.map_err(|e| e.to_string())
+}
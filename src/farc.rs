@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use aes::Aes128;
+use aes::cipher::{BlockDecrypt, KeyInit};
+use aes::cipher::generic_array::GenericArray;
+use crate::file_index::ModFile;
+use crate::mod_catalog::ModCatalog;
+
+//FARC, the archives most of the game's assets are packed in
+//Only the header is read, it has the name and size of every file inside.
+//  FArc - stored as is
+//  FArC - every entry gzipped
+//  FARC - flags for gzip and AES, newer ones also have an entry count and per entry flags.
+//         Future Tone style archives encrypt the header itself with AES-128-CBC, the IV is stored right before it.
+//Everything is big endian.
+
+// A header bigger than this isn't a real archive, don't go allocating it.
+const MAX_HEADER_SIZE: u32 = 64 * 1024 * 1024;
+
+// The key Future Tone and Mega Mix use for encrypted headers, every FARC tool out there has it.
+pub const FUTURE_TONE_KEY: [u8; 16] = [
+    0x13, 0x72, 0xD5, 0x7B, 0x6E, 0x9A, 0x31, 0xEB, 0xA1, 0x4D, 0x3F, 0x8F, 0x1B, 0x53, 0xE8, 0x74,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FarcFormat {
+    // FArc
+    Plain,
+    // FArC
+    Compressed,
+    // FARC
+    Extended { compressed: bool, encrypted: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FarcEntry {
+    pub name: String,
+    pub offset: u32,
+    // Size in the archive, after compression
+    pub size: u32,
+    // Size once extracted, the same as `size` for FArc
+    pub original_size: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Farc {
+    pub format: FarcFormat,
+    pub alignment: u32,
+    pub entries: Vec<FarcEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FarcErrorType {
+    NotFarc,
+    // Cut short, or sizes that make no sense
+    Corrupt,
+    // Encrypted with a key we don't know
+    EncryptedHeader,
+    ReadError(String),
+}
+
+impl Display for FarcErrorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FarcErrorType::NotFarc => write!(f, "Not a FARC archive"),
+            FarcErrorType::Corrupt => write!(f, "The archive header is damaged"),
+            FarcErrorType::EncryptedHeader => write!(f, "The file list is encrypted with an unknown key"),
+            FarcErrorType::ReadError(err) => write!(f, "Cannot read the archive: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FarcError(pub FarcErrorType);
+
+impl Display for FarcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<io::Error> for FarcError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => FarcError(FarcErrorType::Corrupt),
+            _ => FarcError(FarcErrorType::ReadError(err.to_string())),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], FarcError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(FarcError(FarcErrorType::Corrupt))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn peek_u32(&self) -> Option<u32> {
+        let bytes = self.data.get(self.pos..self.pos + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, FarcError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn name(&mut self) -> Result<String, FarcError> {
+        let rest = self.data.get(self.pos..).unwrap_or_default();
+        let len = rest.iter().position(|b| *b == 0).ok_or(FarcError(FarcErrorType::Corrupt))?;
+        let name = String::from_utf8_lossy(&rest[..len]).to_string();
+        self.pos += len + 1;
+        Ok(name)
+    }
+}
+
+impl Farc {
+    // `data` needs to have at least the whole header, the file contents aren't looked at.
+    pub fn parse(data: &[u8]) -> Result<Farc, FarcError> {
+        let decrypted: Vec<u8>;
+        let mut reader = Reader { data, pos: 0 };
+        let signature = reader.bytes(4).map_err(|_| FarcError(FarcErrorType::NotFarc))?;
+        let format = match signature {
+            b"FArc" => FarcFormat::Plain,
+            b"FArC" => FarcFormat::Compressed,
+            b"FARC" => FarcFormat::Extended { compressed: false, encrypted: false },
+            _ => return Err(FarcError(FarcErrorType::NotFarc)),
+        };
+        let header_size = reader.u32()?;
+        let end = 8 + header_size as usize;
+        //Only look at the header from here on
+        reader.data = data.get(..end).ok_or(FarcError(FarcErrorType::Corrupt))?;
+
+        let mut farc = Farc { format, alignment: 0, entries: Vec::new() };
+        //Future Tone entries have flags after the sizes
+        let mut entry_flags = false;
+        //Decrypted headers are padded with zeros, the entry count says where they end
+        let mut padded = false;
+        let mut count = None;
+        if let FarcFormat::Extended { .. } = format {
+            let flags = reader.u32()?;
+            let _padding = reader.u32()?;
+            farc.alignment = reader.u32()?;
+            let encrypted = flags & 4 != 0;
+            farc.format = FarcFormat::Extended { compressed: flags & 2 != 0, encrypted };
+            //Encrypted headers turn the alignment into noise, a real one is always a power of two.
+            //What we read as the alignment is the start of the IV, everything after it is encrypted.
+            if encrypted && !farc.alignment.is_power_of_two() {
+                let header = data.get(0x10..end).ok_or(FarcError(FarcErrorType::Corrupt))?;
+                decrypted = decrypt_header(header)?;
+                reader = Reader { data: &decrypted, pos: 0 };
+                padded = true;
+                farc.alignment = reader.u32()?;
+                //A wrong key gives noise again
+                if !farc.alignment.is_power_of_two() || reader.peek_u32().is_none_or(|v| v > 1) {
+                    return Err(FarcError(FarcErrorType::EncryptedHeader));
+                }
+            }
+            //Newer archives have a version and an entry count here,
+            //older ones go straight to the first name which never starts with a 0 or 1 byte
+            if reader.peek_u32().is_some_and(|v| v <= 1) {
+                entry_flags = reader.u32()? == 1;
+                let entry_count = reader.u32()?;
+                if entry_flags {
+                    let _padding = reader.u32()?;
+                }
+                if padded {
+                    count = Some(entry_count as usize);
+                }
+            }
+        } else {
+            farc.alignment = reader.u32()?;
+        }
+
+        while count.map_or(reader.pos < reader.data.len(), |count| farc.entries.len() < count) {
+            let name = reader.name()?;
+            let offset = reader.u32()?;
+            let size = reader.u32()?;
+            let original_size = match format {
+                FarcFormat::Plain => size,
+                _ => reader.u32()?,
+            };
+            if entry_flags {
+                let _flags = reader.u32()?;
+            }
+            farc.entries.push(FarcEntry { name, offset, size, original_size });
+        }
+        Ok(farc)
+    }
+
+    // Reads just the header of the archive at `path`.
+    pub fn read(path: &Path) -> Result<Farc, FarcError> {
+        let mut file = File::open(path)?;
+        let mut start = [0u8; 8];
+        file.read_exact(&mut start).map_err(|_| FarcError(FarcErrorType::NotFarc))?;
+        if !matches!(&start[..4], b"FArc" | b"FArC" | b"FARC") {
+            return Err(FarcError(FarcErrorType::NotFarc));
+        }
+        let header_size = u32::from_be_bytes([start[4], start[5], start[6], start[7]]);
+        if header_size > MAX_HEADER_SIZE {
+            return Err(FarcError(FarcErrorType::Corrupt));
+        }
+
+        let mut data = start.to_vec();
+        data.resize(8 + header_size as usize, 0);
+        file.read_exact(&mut data[8..])?;
+        Self::parse(&data)
+    }
+}
+
+// AES-128-CBC with the Future Tone key, `header` starts with the IV.
+// A trailing partial block can't be decrypted and is left out.
+fn decrypt_header(header: &[u8]) -> Result<Vec<u8>, FarcError> {
+    if header.len() < 16 {
+        return Err(FarcError(FarcErrorType::Corrupt));
+    }
+    let (iv, body) = header.split_at(16);
+    let cipher = Aes128::new(&GenericArray::from(FUTURE_TONE_KEY));
+    let mut decrypted = Vec::with_capacity(body.len());
+    let mut previous = iv;
+    for chunk in body.chunks_exact(16) {
+        let mut block = GenericArray::clone_from_slice(chunk);
+        cipher.decrypt_block(&mut block);
+        decrypted.extend(block.iter().zip(previous).map(|(b, p)| b ^ p));
+        previous = chunk;
+    }
+    Ok(decrypted)
+}
+
+// A .farc in a mod, and what we could find out about it.
+#[derive(Debug, Clone)]
+pub struct ModFarc {
+    // The game path of the archive, see ModFile
+    pub path: String,
+    pub contents: Result<Farc, FarcError>,
+}
+
+pub fn is_farc(file: &ModFile) -> bool {
+    file.key().ends_with(".farc")
+}
+
+// The headers of every archive in a mod, in the same order as `files`.
+pub fn index_mod(files: &[ModFile]) -> Vec<ModFarc> {
+    files.iter()
+        .filter(|f| is_farc(f))
+        .map(|f| ModFarc { path: f.path.clone(), contents: Farc::read(&f.source) })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveRef {
+    // Folder name of the mod
+    pub folder: String,
+    // Game path of the archive
+    pub archive: String,
+}
+
+// Files inside of archives that more than one enabled mod has,
+// grouped by the archives they're in so a copied archive shows as one overlap.
+#[derive(Debug, Clone)]
+pub struct EntryOverlap {
+    // In load order, highest priority first
+    pub archives: Vec<ArchiveRef>,
+    pub entries: Vec<String>,
+}
+
+// `order` is the load order, highest priority first. Disabled mods are skipped.
+pub fn overlaps(order: &[String], catalog: &ModCatalog) -> Vec<EntryOverlap> {
+    //Lowercase entry name -> (name as written, archives it's in)
+    let mut entries: BTreeMap<String, (String, Vec<ArchiveRef>)> = BTreeMap::new();
+    for entry in order.iter().filter_map(|folder| catalog.find(folder)) {
+        if !entry.config.enabled {
+            continue;
+        }
+        let folder = entry.folder_name();
+        for archive in &entry.archives {
+            let farc = match &archive.contents {
+                Ok(farc) => farc,
+                Err(_) => continue,
+            };
+            for file in &farc.entries {
+                let (_, refs) = entries.entry(file.name.to_lowercase())
+                    .or_insert_with(|| (file.name.clone(), Vec::new()));
+                //The same mod having a file twice is the mod's own business
+                if refs.iter().all(|r| r.folder != folder) {
+                    refs.push(ArchiveRef { folder: folder.clone(), archive: archive.path.clone() });
+                }
+            }
+        }
+    }
+
+    let mut overlaps: Vec<EntryOverlap> = Vec::new();
+    for (name, archives) in entries.into_values().filter(|(_, refs)| refs.len() > 1) {
+        match overlaps.iter_mut().find(|o| o.archives == archives) {
+            Some(overlap) => overlap.entries.push(name),
+            None => overlaps.push(EntryOverlap { archives, entries: vec![name] }),
+        }
+    }
+    overlaps
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::mod_conf::ModConfig;
+
+//File index, the game files a mod replaces
+//DivaModLoader layers every folder in a mod's `include` list over the game,
+//a file at <mod>/<include>/rom/x replaces the game's rom/x.
+//Only the rom folders (rom, rom_steam, rom_steam_en...) are indexed,
+//everything else in a mod (readmes, previews) is never seen by the game.
+
+#[derive(Debug, Clone)]
+pub struct ModFile {
+    // Relative to the include folder, with / separators, e.g rom/2d/spr_gam_cmn.farc
+    pub path: String,
+    // Where the file is on disk
+    pub source: PathBuf,
+}
+
+impl ModFile {
+    // The game runs on Windows (or Proton), paths are compared without case.
+    pub fn key(&self) -> String {
+        self.path.to_lowercase()
+    }
+}
+
+// The folders DivaModLoader reads from when `include` isn't set.
+pub fn include_dirs(config: &ModConfig) -> Vec<String> {
+    match &config.include {
+        Some(include) => include.clone(),
+        None => vec![String::from(".")],
+    }
+}
+
+// Every game file the mod in `mod_dir` provides, sorted by path.
+// When two include folders have the same file the first one in the list is used.
+pub fn index_mod(mod_dir: &Path, config: &ModConfig) -> Vec<ModFile> {
+    let mut files: Vec<ModFile> = Vec::new();
+    let mut seen = HashSet::new();
+    for include in include_dirs(config) {
+        //An include pointing outside of the mod isn't something we should go read
+        if Path::new(&include).components().any(|c| matches!(c, Component::ParentDir | Component::RootDir | Component::Prefix(_))) {
+            continue;
+        }
+        let root = mod_dir.join(&include);
+        let mut found = Vec::new();
+        walk(&root, &root, &mut found);
+        for file in found {
+            if seen.insert(file.key()) {
+                files.push(file);
+            }
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+fn walk(root: &Path, dir: &Path, out: &mut Vec<ModFile>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        //No symlinks, a link back up would never end
+        let file_type = match entry.file_type() {
+            Ok(t) if !t.is_symlink() => t,
+            _ => continue,
+        };
+        let rel = match path.strip_prefix(root) {
+            Ok(rel) => rel,
+            Err(_) => continue,
+        };
+        if !is_game_path(rel, file_type.is_dir()) {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            walk(root, &path, out);
+        } else if file_type.is_file() {
+            let rel: Vec<String> = rel.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+            out.push(ModFile { path: rel.join("/"), source: path });
+        }
+    }
+}
+
+// Only paths inside a rom folder get loaded by the game, that's rom itself or rom_<something>.
+// Other names starting with rom, like a romfs folder or romance.txt, aren't.
+fn is_game_path(rel: &Path, is_dir: bool) -> bool {
+    let mut components = rel.components();
+    let first = match components.next() {
+        Some(Component::Normal(first)) => first.to_string_lossy().to_lowercase(),
+        _ => return false,
+    };
+    //A file right inside the include folder isn't in any rom folder, whatever it's called
+    (first == "rom" || first.starts_with("rom_")) && (is_dir || components.next().is_some())
+}
@@ -0,0 +1,162 @@
+use std::collections::{HashMap, HashSet};
+use iced::{Alignment, Button, button, Checkbox, Color, Column, Element, Length, Row, Scrollable, scrollable, Space, Text};
+use crate::conflicts::{Overlay, TreeNode};
+use crate::mod_catalog::ModCatalog;
+use crate::style::M4ButtonStyleSheet;
+
+//Files tab on the home screen
+//The game files as DivaModLoader will see them with the enabled mods,
+//every file shows the mod it comes from and the mods it hides.
+
+#[derive(Debug, Clone)]
+pub enum FileTreeMessage {
+    // Path of the folder to open or close
+    Toggle(String),
+    ConflictsOnly(bool),
+}
+
+// A line in the tree, only the folders that are open have their contents listed.
+struct TreeRow {
+    depth: u16,
+    name: String,
+    path: String,
+    is_dir: bool,
+    detail: String,
+    is_conflict: bool,
+    toggle_btn: button::State,
+}
+
+#[derive(Default)]
+pub struct FileTree {
+    tree: TreeNode,
+    // Folder name -> the mod's display name
+    names: HashMap<String, String>,
+    expanded: HashSet<String>,
+    conflicts_only: bool,
+    rows: Vec<TreeRow>,
+    scroll: scrollable::State,
+}
+
+impl FileTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // `order` is the current load order, highest priority first.
+    pub fn set_mods(&mut self, order: &[String], catalog: &ModCatalog) {
+        self.tree = Overlay::build(order, catalog).tree();
+        self.names = catalog.mods.iter().map(|m| (m.folder_name(), m.display_name())).collect();
+        self.rebuild();
+    }
+
+    pub fn update(&mut self, message: FileTreeMessage) {
+        match message {
+            FileTreeMessage::Toggle(path) => {
+                if !self.expanded.remove(&path) {
+                    self.expanded.insert(path);
+                }
+            }
+            FileTreeMessage::ConflictsOnly(only) => self.conflicts_only = only,
+        }
+        self.rebuild();
+    }
+
+    fn rebuild(&mut self) {
+        let mut rows = Vec::new();
+        self.push_children(&self.tree, 0, &mut rows);
+        self.rows = rows;
+    }
+
+    fn push_children(&self, node: &TreeNode, depth: u16, rows: &mut Vec<TreeRow>) {
+        for child in &node.children {
+            if self.conflicts_only && child.conflict_count() == 0 {
+                continue;
+            }
+            let name = |folder: &String| self.names.get(folder).cloned().unwrap_or_else(|| folder.clone());
+            let (detail, is_conflict) = match &child.entry {
+                Some(entry) if entry.is_conflict() => {
+                    let losers: Vec<String> = entry.losers().iter().map(name).collect();
+                    (format!("{}, hides {}", name(&entry.providers[0]), losers.join(", ")), true)
+                }
+                Some(entry) if entry.merged => {
+                    let providers: Vec<String> = entry.providers.iter().map(name).collect();
+                    (format!("merged from {}", providers.join(", ")), false)
+                }
+                Some(entry) => (name(&entry.providers[0]), false),
+                None => match child.conflict_count() {
+                    0 => (format!("{} file(s)", child.file_count()), false),
+                    n => (format!("{} file(s), {} conflict(s)", child.file_count(), n), false),
+                },
+            };
+
+            rows.push(TreeRow {
+                depth,
+                name: child.name.clone(),
+                path: child.path.clone(),
+                is_dir: child.is_dir(),
+                detail,
+                is_conflict,
+                toggle_btn: button::State::new(),
+            });
+            if child.is_dir() && self.expanded.contains(&child.path) {
+                self.push_children(child, depth + 1, rows);
+            }
+        }
+    }
+
+    pub fn view(&mut self) -> Element<'_, FileTreeMessage> {
+        let grey = Color::from_rgb(0.4, 0.4, 0.4);
+        let mut content = Column::new()
+            .spacing(10)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(
+                        Text::new("The game files the enabled mods replace, and which mod each one comes from.")
+                            .size(16)
+                            .color(grey)
+                            .width(Length::Fill)
+                    )
+                    .push(Checkbox::new(self.conflicts_only, "Conflicts only", FileTreeMessage::ConflictsOnly).size(16).text_size(16))
+            );
+
+        let mut list = Scrollable::new(&mut self.scroll)
+            .spacing(2)
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        if self.rows.is_empty() {
+            list = list.push(Text::new("No enabled mod replaces any game files."));
+        }
+
+        for row in self.rows.iter_mut() {
+            let indent = Space::new(Length::Units(row.depth * 20), Length::Shrink);
+            let label: Element<_> = if row.is_dir {
+                let arrow = if self.expanded.contains(&row.path) { "v" } else { ">" };
+                Button::new(&mut row.toggle_btn, Text::new(format!("{} {}/", arrow, row.name)).size(16))
+                    .style(M4ButtonStyleSheet)
+                    .padding(0)
+                    .on_press(FileTreeMessage::Toggle(row.path.clone()))
+                    .into()
+            } else {
+                Text::new(row.name.as_str()).size(16).into()
+            };
+            let detail_color = if row.is_conflict { Color::from_rgb(0.8, 0.4, 0.) } else { grey };
+
+            list = list.push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(indent)
+                    .push(label)
+                    .push(Text::new(row.detail.as_str()).size(14).color(detail_color))
+            );
+        }
+
+        content = content.push(list);
+        content.into()
+    }
+}
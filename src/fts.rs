@@ -1,13 +1,17 @@
-use std::path::Path;
-use ashpd::desktop::file_chooser::{FileChooserProxy, OpenFileOptions};
+use std::path::{Path, PathBuf};
+use ashpd::desktop::file_chooser::{FileChooserProxy, FileFilter, OpenFileOptions};
 use ashpd::{WindowIdentifier, zbus};
 use iced::*;
 use iced::button::State;
 use iced::svg::Handle;
 use url::Url;
-use crate::constants;
+use crate::{constants, loader_installer, loader_update, steam};
+use crate::loader_conf::LoaderConfig;
+use crate::loader_installer::InstalledLoader;
+use crate::loader_update::UpdateCheck;
+use crate::m4_settings::M4Settings;
 use crate::style::{M4ButtonStyleSheet, M4InputStyleSheet};
-use crate::util::is_valid_megamix_dir;
+use crate::util::{check_megamix_dir, check_mods_writable, GameDirProblem, has_mod_loader};
 
 //FTS, First time setup
 //This is a big file, so things are in regions, to make it easier to navigate
@@ -25,7 +29,7 @@ pub enum FTSMessage {
     NextClicked,
     FtsStepMsg(StepMessage),
 
-    // Can be used to tell the parent to do something
+    // Setup is finished, the parent fills in its settings with `FirstTimeSetup::apply_to`
     FtsDone,
 }
 
@@ -35,6 +39,12 @@ pub struct FirstTimeSetup {
     next_button: button::State,
 }
 
+impl Default for FirstTimeSetup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FirstTimeSetup {
     pub fn new() -> Self {
         Self {
@@ -44,7 +54,18 @@ impl FirstTimeSetup {
         }
     }
 
-    pub fn view(&mut self) -> Element<FTSMessage> {
+    // Called when setup is shown, looks for the game in the Steam libraries
+    // so the game directory step can be filled in already.
+    // `loader_feed` is where the mod loader step downloads DivaModLoader from.
+    pub fn start(&mut self, loader_feed: &str) -> Command<FTSMessage> {
+        if let Some(Step::ModLoader { feed, .. }) = self.steps.find_mut(|s| matches!(s, Step::ModLoader { .. })) {
+            *feed = loader_feed.to_string();
+        }
+        Command::perform(steam::detect_megamix_dir(),
+                         |dir| FTSMessage::FtsStepMsg(StepMessage::GameDirDetected(dir)))
+    }
+
+    pub fn view(&mut self) -> Element<'_, FTSMessage> {
         let FirstTimeSetup {
             steps,
             prev_button,
@@ -70,6 +91,12 @@ impl FirstTimeSetup {
                     .style(M4ButtonStyleSheet)
                     .on_press(FTSMessage::NextClicked)
             );
+        } else if steps.is_done() {
+            controls = controls.push(
+                Button::new(next_button, Text::new("Finish").size(20))
+                    .style(M4ButtonStyleSheet)
+                    .on_press(FTSMessage::FtsDone)
+            );
         }
 
         let main_content = Container::new(steps.view().map(FTSMessage::FtsStepMsg))
@@ -92,19 +119,21 @@ impl FirstTimeSetup {
             .into()
     }
 
+    // Puts what was picked in the steps into `settings`, the rest is left as it was.
+    pub fn apply_to(&self, settings: &mut M4Settings) {
+        settings.game_dir = self.steps.game_dir();
+    }
+
     pub fn update(&mut self, message: FTSMessage) -> Command<FTSMessage> {
-        return match message {
+        match message {
             FTSMessage::PrevClicked => {
                 self.steps.go_back();
                 Command::none()
             }
-            FTSMessage::NextClicked => {
-                self.steps.advance();
-                Command::none()
-            }
-            FTSMessage::FtsStepMsg(smsg) => self.steps.update(smsg).map(|cmd| FTSMessage::FtsStepMsg(cmd)),
+            FTSMessage::NextClicked => self.steps.advance().map(FTSMessage::FtsStepMsg),
+            FTSMessage::FtsStepMsg(smsg) => self.steps.update(smsg).map(FTSMessage::FtsStepMsg),
             _ => { Command::none() }
-        };
+        }
     }
 }
 //endregion
@@ -116,10 +145,47 @@ enum Step {
         text_state: text_input::State,
         browse_btn: button::State,
         dir: String,
+        // Where Steam says the game is, if it knows
+        detected: Option<String>,
+        // What's wrong with `dir`, checked when it changes
+        check: std::result::Result<(), GameDirProblem>,
+        // Why the mods folder couldn't be written the last time Next was pressed.
+        // Kept apart from `check` so Next stays usable and tries again, e.g after fixing the permissions
+        not_writable: Option<GameDirProblem>,
+    },
+    ModLoader {
+        // Set when the step is shown, from the game directory step
+        game_dir: String,
+        feed: String,
+        status: Option<LoaderStatus>,
+        // The latest release compared to what's installed, None while it's being checked
+        update: Option<std::result::Result<UpdateCheck, String>>,
+        installing: bool,
+        // How the last install went
+        message: Option<String>,
+        file_btn: button::State,
+        feed_btn: button::State,
     },
     End,
 }
 
+// What's in the game directory, read every time the mod loader step is shown.
+struct LoaderStatus {
+    installed: bool,
+    version: Option<String>,
+    config: std::result::Result<LoaderConfig, String>,
+}
+
+impl LoaderStatus {
+    fn read(game_dir: &Path) -> Self {
+        Self {
+            installed: has_mod_loader(game_dir),
+            version: loader_installer::installed_version(game_dir),
+            config: LoaderConfig::load(game_dir).map_err(|e| e.to_string()),
+        }
+    }
+}
+
 struct Steps {
     steps: Vec<Step>,
     current: usize,
@@ -134,6 +200,19 @@ impl Steps {
                     text_state: text_input::State::new(),
                     browse_btn: button::State::new(),
                     dir: String::new(),
+                    detected: None,
+                    check: Err(GameDirProblem::NotFound),
+                    not_writable: None,
+                },
+                Step::ModLoader {
+                    game_dir: String::new(),
+                    feed: String::from(constants::LOADER_RELEASE_FEED),
+                    status: None,
+                    update: None,
+                    installing: false,
+                    message: None,
+                    file_btn: button::State::new(),
+                    feed_btn: button::State::new(),
                 },
                 Step::End,
             ],
@@ -142,16 +221,53 @@ impl Steps {
     }
 
     fn update(&mut self, msg: StepMessage) -> Command<StepMessage> {
-        self.steps[self.current].update(msg)
+        //Detection and installs finish in the background, so they go to their step even when that isn't shown
+        let step = match msg {
+            StepMessage::GameDirDetected(_) => self.find_mut(|s| matches!(s, Step::FindGameDirectory { .. })),
+            StepMessage::LoaderArchiveChosen(_) | StepMessage::LoaderChecked(..) | StepMessage::LoaderInstalled(_) => {
+                self.find_mut(|s| matches!(s, Step::ModLoader { .. }))
+            }
+            _ => self.steps.get_mut(self.current),
+        };
+        match step {
+            Some(step) => step.update(msg),
+            None => Command::none(),
+        }
     }
 
-    fn view(&mut self) -> Element<StepMessage> {
+    fn find_mut(&mut self, pred: impl Fn(&Step) -> bool) -> Option<&mut Step> {
+        self.steps.iter_mut().find(|s| pred(s))
+    }
+
+    fn view(&mut self) -> Element<'_, StepMessage> {
         self.steps[self.current].view()
     }
 
-    fn advance(&mut self) {
-        if self.can_continue() {
-            self.current += 1;
+    fn advance(&mut self) -> Command<StepMessage> {
+        //Writing a test file on every keystroke would be a bit much, it's tried once the folder is picked
+        if let Some(Step::FindGameDirectory { dir, check, not_writable, .. }) = self.steps.get_mut(self.current) {
+            if check.is_ok() {
+                *not_writable = check_mods_writable(Path::new(dir)).err();
+                if not_writable.is_some() {
+                    return Command::none();
+                }
+            }
+        }
+        if !self.can_continue() {
+            return Command::none();
+        }
+        self.current += 1;
+        //The game directory may have changed since the last time
+        let dir = self.game_dir();
+        match &mut self.steps[self.current] {
+            Step::ModLoader { game_dir, feed, status, update, message, .. } => {
+                *status = Some(LoaderStatus::read(Path::new(&dir)));
+                *game_dir = dir;
+                *update = None;
+                *message = None;
+                Step::check_for_update(game_dir, feed)
+            }
+            _ => Command::none(),
         }
     }
 
@@ -170,12 +286,35 @@ impl Steps {
         self.current + 1 < self.steps.len()
             && self.steps[self.current].can_continue()
     }
+
+    // On the last step, with everything before it filled in.
+    fn is_done(&self) -> bool {
+        self.current + 1 == self.steps.len()
+            && self.steps[..self.current].iter().all(|s| s.can_continue())
+    }
+
+    fn game_dir(&self) -> String {
+        self.steps.iter()
+            .find_map(|s| match s {
+                Step::FindGameDirectory { dir, .. } => Some(dir.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum StepMessage {
     GameDirChanged(String),
+    GameDirDetected(Option<String>),
     BrowseClicked,
+    LoaderFileClicked,
+    LoaderArchiveChosen(Option<PathBuf>),
+    LoaderCheckClicked,
+    // The game directory it was for, and the result
+    LoaderChecked(String, std::result::Result<UpdateCheck, String>),
+    LoaderDownloadClicked,
+    LoaderInstalled(std::result::Result<InstalledLoader, String>),
 }
 
 //Text To Content
@@ -190,39 +329,119 @@ impl<'a> Step {
     fn can_continue(&self) -> bool {
         //Thins like the game directory setup should check if the given data is a
         match self {
-            Step::FindGameDirectory { dir, .. } => is_valid_megamix_dir(Path::new(dir)),
+            Step::FindGameDirectory { check, .. } => check.is_ok(),
+            //The loader is optional, M4 works without it, the game just won't load the mods
+            Step::ModLoader { installing, .. } => !*installing,
             Step::End => false,
             _ => true,
         }
     }
 
     fn can_go_back(&self) -> bool {
-        match self {
-            Step::Welcome => false,
-            _ => true,
-        }
+        !matches!(self, Step::Welcome | Step::ModLoader { installing: true, .. })
     }
 
-    fn view(&mut self) -> Element<StepMessage> {
+    fn view(&mut self) -> Element<'_, StepMessage> {
         match self {
             Step::Welcome => Self::welcome(),
-            Step::FindGameDirectory { text_state, browse_btn, dir } => Self::find_game_directory(text_state, browse_btn, dir),
+            Step::FindGameDirectory { text_state, browse_btn, dir, detected, check, not_writable } => {
+                Self::find_game_directory(text_state, browse_btn, dir, detected, check, not_writable)
+            }
+            Step::ModLoader { status, update, installing, message, file_btn, feed_btn, .. } => {
+                Self::mod_loader(status, update, *installing, message, file_btn, feed_btn)
+            }
             Step::End => Self::end()
         }
     }
 
     fn update(&mut self, msg: StepMessage) -> Command<StepMessage> {
-        return match msg {
+        match msg {
             StepMessage::GameDirChanged(input) => {
-                if let Step::FindGameDirectory { dir, .. } = self {
+                if let Step::FindGameDirectory { dir, check, not_writable, .. } = self {
+                    *check = check_megamix_dir(Path::new(&input));
+                    *not_writable = None;
                     *dir = input;
                 }
                 Command::none()
             }
+            StepMessage::GameDirDetected(found) => {
+                if let Step::FindGameDirectory { dir, detected, check, not_writable, .. } = self {
+                    //Don't overwrite what the user already typed
+                    if let (true, Some(found)) = (dir.is_empty(), &found) {
+                        *dir = found.clone();
+                        *check = check_megamix_dir(Path::new(dir));
+                        *not_writable = None;
+                    }
+                    *detected = found;
+                }
+                Command::none()
+            }
             StepMessage::BrowseClicked => {
                 Command::perform(Self::browse_for_dir(), StepMessage::GameDirChanged)
             }
-        };
+            StepMessage::LoaderFileClicked => {
+                Command::perform(Self::browse_for_loader(), StepMessage::LoaderArchiveChosen)
+            }
+            StepMessage::LoaderArchiveChosen(archive) => {
+                match (self, archive) {
+                    (Step::ModLoader { game_dir, installing, message, .. }, Some(archive)) => {
+                        *installing = true;
+                        *message = Some(format!("Installing {}...", archive.display()));
+                        Command::perform(
+                            loader_installer::install(archive, PathBuf::from(game_dir.as_str()), None),
+                            |res| StepMessage::LoaderInstalled(res.map_err(|e| e.to_string())))
+                    }
+                    _ => Command::none(),
+                }
+            }
+            StepMessage::LoaderCheckClicked => {
+                match self {
+                    Step::ModLoader { game_dir, feed, update, .. } => {
+                        *update = None;
+                        Self::check_for_update(game_dir, feed)
+                    }
+                    _ => Command::none(),
+                }
+            }
+            StepMessage::LoaderChecked(checked_dir, res) => {
+                if let Step::ModLoader { game_dir, update, .. } = self {
+                    //The user went back and picked another folder in the meantime
+                    if *game_dir == checked_dir {
+                        *update = Some(res);
+                    }
+                }
+                Command::none()
+            }
+            StepMessage::LoaderDownloadClicked => {
+                match self {
+                    Step::ModLoader { game_dir, update: Some(Ok(check)), installing, message, .. } if check.update_available() => {
+                        *installing = true;
+                        *message = Some(format!("Downloading DivaModLoader {}...", check.latest.version));
+                        Command::perform(
+                            loader_update::update_loader(PathBuf::from(game_dir.as_str()), check.latest.clone()),
+                            |res| StepMessage::LoaderInstalled(res.map_err(|e| e.to_string())))
+                    }
+                    _ => Command::none(),
+                }
+            }
+            StepMessage::LoaderInstalled(res) => {
+                if let Step::ModLoader { game_dir, status, update, installing, message, .. } = self {
+                    *installing = false;
+                    *message = Some(match res {
+                        Ok(installed) => match installed.version {
+                            Some(version) => format!("Installed DivaModLoader {}.", version),
+                            None => String::from("Installed DivaModLoader."),
+                        },
+                        Err(err) => err,
+                    });
+                    *status = Some(LoaderStatus::read(Path::new(game_dir)));
+                    if let (Some(Ok(check)), Some(status)) = (update, status) {
+                        check.installed = status.version.clone();
+                    }
+                }
+                Command::none()
+            }
+        }
     }
 
     fn welcome() -> Element<'a, StepMessage> {
@@ -234,7 +453,19 @@ impl<'a> Step {
             .into()
     }
 
-    fn find_game_directory(input_state: &'a mut text_input::State, browse_btn: &'a mut State, dir: &mut String) -> Element<'a, StepMessage> {
+    fn find_game_directory(input_state: &'a mut text_input::State, browse_btn: &'a mut State, dir: &mut String, detected: &Option<String>,
+                           check: &std::result::Result<(), GameDirProblem>, not_writable: &Option<GameDirProblem>) -> Element<'a, StepMessage> {
+        let hint = match detected {
+            Some(found) if found == dir => "Found Mega Mix+ in your Steam library.",
+            _ => "",
+        };
+        //Nothing typed yet isn't worth complaining about
+        let problem = match check {
+            Err(problem) if !dir.trim().is_empty() => Some(problem),
+            Err(_) => None,
+            Ok(()) => not_writable.as_ref(),
+        };
+
         Column::new()
             .spacing(15)
             .padding(40)
@@ -242,10 +473,10 @@ impl<'a> Step {
             .push(Space::new(Length::Units(TTC_SPACING), Length::Shrink))
             .push(
                 Row::new()
-                    .push(TextInput::new(input_state, "Game directory", dir, |s| StepMessage::GameDirChanged(s))
+                    .push(TextInput::new(input_state, "Game directory", dir, StepMessage::GameDirChanged)
                         .style(M4InputStyleSheet {
                             show_validity: true,
-                            is_valid: is_valid_megamix_dir(Path::new(dir)),
+                            is_valid: check.is_ok() && not_writable.is_none(),
                         })
                         .padding(3).width(Length::FillPortion(2)))
                     .push(Button::new(browse_btn,
@@ -258,17 +489,109 @@ impl<'a> Step {
                         .on_press(StepMessage::BrowseClicked))
                     .align_items(Alignment::Center)
             )
-
+            .push(match problem {
+                Some(problem) => Column::new()
+                    .spacing(5)
+                    .push(Text::new(problem.to_string()).size(16).color(Color::from_rgb(0.8, 0., 0.)))
+                    .push(Text::new(problem.suggestion()).size(16).color(Color::from_rgb(0.4, 0.4, 0.4))),
+                None => Column::new().push(Text::new(hint).size(16)),
+            })
             .into()
     }
 
+    fn mod_loader(status: &Option<LoaderStatus>, update: &Option<std::result::Result<UpdateCheck, String>>, installing: bool,
+                  message: &Option<String>, file_btn: &'a mut State, feed_btn: &'a mut State) -> Element<'a, StepMessage> {
+        let grey = Color::from_rgb(0.4, 0.4, 0.4);
+        let mut content = Column::new()
+            .spacing(15)
+            .padding(40)
+            .push(Text::new("Mod loader").size(42))
+            .push(Space::new(Length::Units(TTC_SPACING), Length::Shrink));
+
+        if let Some(status) = status {
+            if status.installed {
+                let version = status.version.as_deref().unwrap_or("(unknown version)");
+                content = content.push(Text::new(format!("DivaModLoader {} is installed.", version)));
+                content = content.push(match &status.config {
+                    Ok(config) => Text::new(format!("{}, mods folder \"{}\", {} mod(s) in the priority list",
+                                                    if config.enabled { "Enabled" } else { "Disabled" },
+                                                    config.mods, config.priority.len()))
+                        .size(16)
+                        .color(grey),
+                    Err(err) => Text::new(err.as_str()).size(16).color(Color::from_rgb(0.8, 0., 0.)),
+                });
+            } else {
+                content = content.push(Text::new("DivaModLoader isn't installed, the game won't load any mods without it."));
+            }
+        }
+
+        //Only offer a download when there's something newer to get
+        let feed_action = match update {
+            None => {
+                content = content.push(Text::new("Checking for the latest release...").size(16).color(grey));
+                None
+            }
+            Some(Ok(check)) if check.update_available() => {
+                content = content.push(Text::new(format!("DivaModLoader {} is available.", check.latest.version)));
+                let label = if status.as_ref().is_some_and(|s| s.installed) { "Update" } else { "Download" };
+                Some((format!("{} to {}", label, check.latest.version), StepMessage::LoaderDownloadClicked))
+            }
+            Some(Ok(check)) => {
+                content = content.push(Text::new(format!("This is the latest release ({}).", check.latest.version)).size(16).color(grey));
+                None
+            }
+            Some(Err(err)) => {
+                content = content.push(Text::new(format!("Cannot check for the latest release: {}", err)).size(16).color(Color::from_rgb(0.8, 0., 0.)));
+                Some((String::from("Check again"), StepMessage::LoaderCheckClicked))
+            }
+        };
+
+        let button = |state, label: String, msg| {
+            let btn = Button::new(state, Text::new(label).size(20)).style(M4ButtonStyleSheet);
+            if installing { btn } else { btn.on_press(msg) }
+        };
+        let mut buttons = Row::new()
+            .spacing(10)
+            .push(button(file_btn, String::from("Install from a file"), StepMessage::LoaderFileClicked));
+        if let Some((label, msg)) = feed_action {
+            buttons = buttons.push(button(feed_btn, label, msg));
+        }
+        content = content.push(buttons);
+
+        if let Some(message) = message {
+            content = content.push(Text::new(message.as_str()).size(16));
+        }
+        content = content.push(Text::new("You can skip this and install it later.").size(16).color(grey));
+        content.into()
+    }
+
     fn end() -> Element<'a, StepMessage> {
         Column::new()
             .spacing(15)
-            .push(Text::new("Setup complete!"))
+            .push(Text::new("Setup complete!").size(42))
+            .push(Space::new(Length::Units(TTC_SPACING), Length::Shrink))
+            .push(Text::new("Press Finish to start managing your mods."))
             .into()
     }
 
+    fn check_for_update(game_dir: &str, feed: &str) -> Command<StepMessage> {
+        let dir = game_dir.to_string();
+        Command::perform(
+            loader_update::check_for_update(PathBuf::from(game_dir), feed.to_string()),
+            move |res| StepMessage::LoaderChecked(dir.clone(), res.map_err(|e| e.to_string())))
+    }
+
+    async fn browse_for_loader() -> Option<PathBuf> {
+        let connection = zbus::Connection::session().await.ok()?;
+        let proxy = FileChooserProxy::new(&connection).await.ok()?;
+        let files = proxy.open_file(&WindowIdentifier::None, "Select a DivaModLoader release",
+                                    OpenFileOptions::default()
+                                        .accept_label("Install")
+                                        .add_filter(FileFilter::new("DivaModLoader release").mimetype("application/zip")),
+        ).await.ok()?;
+        Url::parse(files.uris().first()?).ok()?.to_file_path().ok()
+    }
+
     async fn browse_for_dir() -> String {
         let connection = zbus::Connection::session().await.unwrap();
         let proxy = FileChooserProxy::new(&connection).await.unwrap();
@@ -277,8 +600,8 @@ impl<'a> Step {
                                       .directory(true)
                                       .accept_label("Select"),
         ).await;
-        if res.is_ok() {
-            return Url::parse(res.unwrap().uris()[0].clone().as_str()).unwrap().to_file_path().unwrap().to_str().unwrap().to_string();
+        if let Ok(files) = res {
+            return Url::parse(files.uris()[0].clone().as_str()).unwrap().to_file_path().unwrap().to_str().unwrap().to_string();
         }
         String::new()
     }
@@ -0,0 +1,520 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use ashpd::desktop::file_chooser::{FileChooserProxy, FileFilter, OpenFileOptions};
+use ashpd::{WindowIdentifier, zbus};
+use iced::{Alignment, Button, button, Color, Column, Command, Element, Length, Row, Scrollable, scrollable, Space, Subscription, Text, Toggler};
+use crate::constants;
+use crate::conflict_list::{ConflictList, ConflictMessage};
+use crate::file_tree::{FileTree, FileTreeMessage};
+use crate::installer;
+use crate::installer::{ConflictAction, InstalledMod, InstallError, InstallErrorType};
+use crate::loader_conf::LoaderConfig;
+use crate::m4_settings::LoadError;
+use crate::mod_catalog::{ModCatalog, ModEntry};
+use crate::mod_conf::ModConfig;
+use crate::priority::{PriorityList, PriorityMessage};
+use crate::profile_list::{ProfileList, ProfileMessage};
+use crate::style::M4ButtonStyleSheet;
+use url::Url;
+
+//Home, the main screen of M4
+//Lists every mod in the mods folder and lets the user turn them on and off,
+//the load order tab shows the priority list, the conflicts tab the files enabled mods fight over,
+//the files tab the merged game files and the profiles tab saves and applies setups.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HomeTab {
+    Mods,
+    LoadOrder,
+    Conflicts,
+    Files,
+    Profiles,
+}
+
+#[derive(Debug, Clone)]
+pub enum HomeMessage {
+    CatalogLoaded(Result<(LoaderConfig, ModCatalog), LoadError>),
+    // Folder name of the mod, and the new enabled state
+    ToggleMod(String, bool),
+    // Folder name of the mod, the enabled state from before the toggle, and how the save went
+    ModSaved(String, bool, Result<bool, String>),
+    ToggleLoader(bool),
+    // The enabled state from before the toggle, and how the save went
+    LoaderSaved(bool, Result<bool, String>),
+    TabSelected(HomeTab),
+    Priority(PriorityMessage),
+    Profiles(ProfileMessage),
+    Conflicts(ConflictMessage),
+    Files(FileTreeMessage),
+    // Folder name of the mod whose archives to show or hide
+    ToggleArchives(String),
+    InstallClicked,
+    ArchiveChosen(Option<PathBuf>),
+    Installed(PathBuf, Result<InstalledMod, InstallError>),
+    // None cancels the install
+    ResolveConflict(Option<ConflictAction>),
+}
+
+// An install that stopped because the mod folder already exists,
+// waiting for the user to pick what to do.
+struct InstallConflict {
+    archive: PathBuf,
+    folder: String,
+    overwrite_btn: button::State,
+    keep_both_btn: button::State,
+    upgrade_btn: button::State,
+    cancel_btn: button::State,
+}
+
+pub struct Home {
+    game_dir: PathBuf,
+    loader: LoaderConfig,
+    catalog: Option<ModCatalog>,
+    status: Option<String>,
+    scroll: scrollable::State,
+    tab: HomeTab,
+    mods_tab_btn: button::State,
+    order_tab_btn: button::State,
+    conflicts_tab_btn: button::State,
+    files_tab_btn: button::State,
+    profiles_tab_btn: button::State,
+    install_btn: button::State,
+    installing: bool,
+    conflict: Option<InstallConflict>,
+    priority: PriorityList,
+    profiles: ProfileList,
+    conflicts: ConflictList,
+    files: FileTree,
+    // One per mod in the catalog
+    archive_btns: Vec<button::State>,
+    // Folder names of the mods with their archives listed
+    open_archives: HashSet<String>,
+}
+
+impl Default for Home {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Home {
+    pub fn new() -> Self {
+        Self {
+            game_dir: PathBuf::new(),
+            loader: LoaderConfig::default(),
+            catalog: None,
+            status: None,
+            scroll: scrollable::State::new(),
+            tab: HomeTab::Mods,
+            mods_tab_btn: button::State::new(),
+            order_tab_btn: button::State::new(),
+            conflicts_tab_btn: button::State::new(),
+            files_tab_btn: button::State::new(),
+            profiles_tab_btn: button::State::new(),
+            install_btn: button::State::new(),
+            installing: false,
+            conflict: None,
+            priority: PriorityList::new(),
+            profiles: ProfileList::new(),
+            conflicts: ConflictList::new(),
+            files: FileTree::new(),
+            archive_btns: Vec::new(),
+            open_archives: HashSet::new(),
+        }
+    }
+
+    // Starts (re)scanning the mods folder of the given game directory.
+    pub fn load(&mut self, game_dir: &Path) -> Command<HomeMessage> {
+        self.game_dir = game_dir.to_path_buf();
+        Command::perform(Self::load_mods(self.game_dir.clone()), HomeMessage::CatalogLoaded)
+    }
+
+    // The loader config decides where the mods folder is, so it's read first.
+    async fn load_mods(game_dir: PathBuf) -> Result<(LoaderConfig, ModCatalog), LoadError> {
+        let loader = LoaderConfig::load(&game_dir)?;
+        let catalog = ModCatalog::load(loader.mods_dir(&game_dir)).await?;
+        Ok((loader, catalog))
+    }
+
+    pub fn update(&mut self, message: HomeMessage) -> Command<HomeMessage> {
+        match message {
+            HomeMessage::CatalogLoaded(catalog) => {
+                match catalog {
+                    Ok((loader, catalog)) => {
+                        self.priority.set_mods(&self.game_dir, &loader, &catalog);
+                        self.conflicts.set_mods(&self.priority.order(), &catalog);
+                        self.files.set_mods(&self.priority.order(), &catalog);
+                        self.archive_btns.resize_with(catalog.mods.len(), button::State::new);
+                        let profiles = self.profiles.set_mods(&self.game_dir, &catalog);
+                        self.loader = loader;
+                        self.catalog = Some(catalog);
+                        self.status = None;
+                        profiles.map(HomeMessage::Profiles)
+                    }
+                    Err(err) => {
+                        self.catalog = None;
+                        self.status = Some(err.to_string());
+                        Command::none()
+                    }
+                }
+            }
+            HomeMessage::ToggleMod(folder, enabled) => {
+                let (config_path, previous) = match self.find_mod_mut(&folder) {
+                    Some(entry) => {
+                        //Update right away so the toggle feels instant,
+                        //ModSaved will put it back if the write fails.
+                        let previous = entry.config.enabled;
+                        entry.config.enabled = enabled;
+                        (entry.config_path(), previous)
+                    }
+                    None => return Command::none(),
+                };
+
+                Command::perform(
+                    ModConfig::save_enabled(config_path, enabled),
+                    move |res| HomeMessage::ModSaved(folder.clone(), previous, res))
+            }
+            HomeMessage::ModSaved(folder, previous, res) => {
+                if let Err(err) = res {
+                    //Not a flip, another toggle of the same mod may have landed in the meantime
+                    if let Some(entry) = self.find_mod_mut(&folder) {
+                        entry.config.enabled = previous;
+                    }
+                    self.status = Some(format!("Failed to save {}: {}", folder, err));
+                }
+                Command::none()
+            }
+            HomeMessage::ToggleLoader(enabled) => {
+                let previous = self.loader.enabled;
+                self.loader.enabled = enabled;
+                Command::perform(
+                    LoaderConfig::save_enabled(self.game_dir.clone(), enabled),
+                    move |res| HomeMessage::LoaderSaved(previous, res))
+            }
+            HomeMessage::LoaderSaved(previous, res) => {
+                if let Err(err) = res {
+                    self.loader.enabled = previous;
+                    self.status = Some(format!("Failed to save the mod loader config: {}", err));
+                }
+                Command::none()
+            }
+            HomeMessage::TabSelected(tab) => {
+                self.tab = tab;
+                //Mods may have been toggled or moved since the last time
+                if let (HomeTab::Conflicts | HomeTab::Files, Some(catalog)) = (tab, &self.catalog) {
+                    self.conflicts.set_mods(&self.priority.order(), catalog);
+                    self.files.set_mods(&self.priority.order(), catalog);
+                }
+                Command::none()
+            }
+            HomeMessage::Conflicts(msg) => {
+                self.conflicts.update(msg);
+                Command::none()
+            }
+            HomeMessage::Files(msg) => {
+                self.files.update(msg);
+                Command::none()
+            }
+            HomeMessage::ToggleArchives(folder) => {
+                if !self.open_archives.remove(&folder) {
+                    self.open_archives.insert(folder);
+                }
+                Command::none()
+            }
+            HomeMessage::Priority(msg) => {
+                self.priority.update(msg).map(HomeMessage::Priority)
+            }
+            HomeMessage::Profiles(msg) => {
+                let reload = ProfileList::needs_reload(&msg);
+                let cmd = self.profiles.update(msg).map(HomeMessage::Profiles);
+                if reload {
+                    let game_dir = self.game_dir.clone();
+                    return Command::batch(vec![cmd, self.load(&game_dir)]);
+                }
+                cmd
+            }
+            HomeMessage::InstallClicked => {
+                Command::perform(Self::browse_for_archive(), HomeMessage::ArchiveChosen)
+            }
+            HomeMessage::ArchiveChosen(archive) => {
+                match archive {
+                    Some(archive) => self.start_install(archive, None),
+                    None => Command::none(),
+                }
+            }
+            HomeMessage::Installed(archive, res) => {
+                self.installing = false;
+                match res {
+                    Ok(_) => {
+                        self.status = None;
+                        let game_dir = self.game_dir.clone();
+                        self.load(&game_dir)
+                    }
+                    Err(InstallError(InstallErrorType::AlreadyInstalled(folder))) => {
+                        self.status = None;
+                        self.conflict = Some(InstallConflict {
+                            archive,
+                            folder,
+                            overwrite_btn: button::State::new(),
+                            keep_both_btn: button::State::new(),
+                            upgrade_btn: button::State::new(),
+                            cancel_btn: button::State::new(),
+                        });
+                        Command::none()
+                    }
+                    Err(err) => {
+                        self.status = Some(err.to_string());
+                        Command::none()
+                    }
+                }
+            }
+            HomeMessage::ResolveConflict(action) => {
+                match (self.conflict.take(), action) {
+                    (Some(conflict), Some(action)) => self.start_install(conflict.archive, Some(action)),
+                    _ => Command::none(),
+                }
+            }
+        }
+    }
+
+    fn start_install(&mut self, archive: PathBuf, on_conflict: Option<ConflictAction>) -> Command<HomeMessage> {
+        let mods_dir = match &self.catalog {
+            Some(catalog) => catalog.mods_dir.clone(),
+            None => return Command::none(),
+        };
+        self.installing = true;
+        self.status = Some(format!("Installing {}...", archive.display()));
+        Command::perform(
+            installer::install(archive.clone(), mods_dir, on_conflict),
+            move |res| HomeMessage::Installed(archive.clone(), res))
+    }
+
+    pub fn subscription(&self) -> Subscription<HomeMessage> {
+        match self.tab {
+            HomeTab::LoadOrder => self.priority.subscription().map(HomeMessage::Priority),
+            HomeTab::Mods | HomeTab::Conflicts | HomeTab::Files | HomeTab::Profiles => Subscription::none(),
+        }
+    }
+
+    pub fn view(&mut self) -> Element<'_, HomeMessage> {
+        let Home {
+            loader,
+            catalog,
+            status,
+            scroll,
+            tab,
+            mods_tab_btn,
+            order_tab_btn,
+            conflicts_tab_btn,
+            files_tab_btn,
+            profiles_tab_btn,
+            install_btn,
+            installing,
+            conflict,
+            priority,
+            profiles,
+            conflicts,
+            files,
+            archive_btns,
+            open_archives,
+            ..
+        } = self;
+
+        let mut content = Column::new()
+            .spacing(10)
+            .padding(20)
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        let catalog = match catalog {
+            Some(catalog) => catalog,
+            None => {
+                let msg = status.clone().unwrap_or_else(|| String::from("Loading mods..."));
+                return content.push(Text::new(msg).size(30)).into();
+            }
+        };
+
+        content = content.push(
+            Row::new()
+                .spacing(10)
+                .align_items(Alignment::Center)
+                .push(Text::new(format!("Mods ({})", catalog.mods.len())).size(42))
+                .push(Self::tab_button(mods_tab_btn, "Mods", HomeTab::Mods, *tab))
+                .push(Self::tab_button(order_tab_btn, "Load order", HomeTab::LoadOrder, *tab))
+                .push(Self::tab_button(conflicts_tab_btn, "Conflicts", HomeTab::Conflicts, *tab))
+                .push(Self::tab_button(files_tab_btn, "Files", HomeTab::Files, *tab))
+                .push(Self::tab_button(profiles_tab_btn, "Profiles", HomeTab::Profiles, *tab))
+                .push(Space::new(Length::Fill, Length::Shrink))
+                .push({
+                    let btn = Button::new(install_btn, Text::new("Install mod").size(20))
+                        .style(M4ButtonStyleSheet);
+                    if *installing { btn } else { btn.on_press(HomeMessage::InstallClicked) }
+                })
+                .push(
+                    Toggler::new(loader.enabled, String::from("Mod loader"), HomeMessage::ToggleLoader)
+                        .width(Length::Shrink)
+                        .spacing(10)
+                )
+        );
+
+        if let Some(status) = status {
+            content = content.push(Text::new(status.as_str()).color(Color::from_rgb(0.8, 0., 0.)));
+        }
+
+        if let Some(conflict) = conflict {
+            content = content.push(Self::conflict_prompt(conflict));
+        }
+
+        match tab {
+            HomeTab::LoadOrder => return content.push(priority.view().map(HomeMessage::Priority)).into(),
+            HomeTab::Conflicts => return content.push(conflicts.view().map(HomeMessage::Conflicts)).into(),
+            HomeTab::Files => return content.push(files.view().map(HomeMessage::Files)).into(),
+            HomeTab::Profiles => return content.push(profiles.view().map(HomeMessage::Profiles)).into(),
+            HomeTab::Mods => {}
+        }
+
+        let mut list = Scrollable::new(scroll)
+            .spacing(15)
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        if catalog.mods.is_empty() && catalog.errors.is_empty() {
+            list = list.push(Text::new(format!("No mods found in {}", catalog.mods_dir.display())));
+        }
+
+        for (entry, archive_btn) in catalog.mods.iter().zip(archive_btns.iter_mut()) {
+            let show_archives = open_archives.contains(&entry.folder_name());
+            list = list.push(Self::mod_row(entry, archive_btn, show_archives));
+        }
+
+        for err in &catalog.errors {
+            list = list.push(
+                Text::new(err.to_string())
+                    .size(16)
+                    .color(Color::from_rgb(0.8, 0., 0.))
+            );
+        }
+
+        content.push(list).into()
+    }
+
+    fn conflict_prompt(conflict: &mut InstallConflict) -> Element<'_, HomeMessage> {
+        let choice = |state, label, action| Button::new(state, Text::new(label))
+            .style(M4ButtonStyleSheet)
+            .on_press(HomeMessage::ResolveConflict(action));
+
+        Column::new()
+            .spacing(5)
+            .push(Text::new(format!("\"{}\" is already installed, what should happen to it?", conflict.folder)))
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(choice(&mut conflict.upgrade_btn, "Upgrade (keep settings)", Some(ConflictAction::Upgrade)))
+                    .push(choice(&mut conflict.overwrite_btn, "Overwrite", Some(ConflictAction::Overwrite)))
+                    .push(choice(&mut conflict.keep_both_btn, "Keep both", Some(ConflictAction::KeepBoth)))
+                    .push(choice(&mut conflict.cancel_btn, "Cancel", None))
+            )
+            .into()
+    }
+
+    fn tab_button<'a>(state: &'a mut button::State, label: &str, tab: HomeTab, current: HomeTab) -> Button<'a, HomeMessage> {
+        let color = if tab == current { *constants::M4BLUE } else { Color::from_rgb(0.4, 0.4, 0.4) };
+        Button::new(state, Text::new(label).size(20).color(color))
+            .style(M4ButtonStyleSheet)
+            .on_press(HomeMessage::TabSelected(tab))
+    }
+
+    fn mod_row<'a>(entry: &ModEntry, archive_btn: &'a mut button::State, show_archives: bool) -> Element<'a, HomeMessage> {
+        let config = &entry.config;
+        let folder = entry.folder_name();
+        let grey = Color::from_rgb(0.4, 0.4, 0.4);
+
+        let mut details = Column::new()
+            .spacing(3)
+            .width(Length::Fill)
+            .push(Text::new(entry.display_name()).size(24));
+
+        let mut byline = Vec::new();
+        if let Some(author) = &config.author {
+            byline.push(format!("by {}", author));
+        }
+        if let Some(version) = &config.version {
+            byline.push(format!("v{}", version));
+        }
+        if !byline.is_empty() {
+            details = details.push(
+                Text::new(byline.join(" - "))
+                    .size(16)
+                    .color(grey)
+            );
+        }
+
+        if let Some(description) = &config.description {
+            details = details.push(Text::new(description.as_str()).size(16));
+        }
+
+        if !entry.archives.is_empty() {
+            let inside: usize = entry.archives.iter()
+                .filter_map(|a| a.contents.as_ref().ok())
+                .map(|farc| farc.entries.len())
+                .sum();
+            details = details.push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(format!("{} archive(s), {} file(s) inside", entry.archives.len(), inside)).size(16).color(grey))
+                    .push(
+                        Button::new(archive_btn, Text::new(if show_archives { "Hide" } else { "Show" }).size(16))
+                            .style(M4ButtonStyleSheet)
+                            .padding(0)
+                            .on_press(HomeMessage::ToggleArchives(folder.clone()))
+                    )
+            );
+        }
+
+        if show_archives {
+            for archive in &entry.archives {
+                match &archive.contents {
+                    Ok(farc) => {
+                        details = details.push(Text::new(format!("{} ({} file(s))", archive.path, farc.entries.len())).size(16));
+                        for file in &farc.entries {
+                            details = details.push(Text::new(format!("    {}", file.name)).size(14).color(grey));
+                        }
+                    }
+                    Err(err) => {
+                        details = details.push(Text::new(format!("{}: {}", archive.path, err)).size(16).color(grey));
+                    }
+                }
+            }
+        }
+
+        Row::new()
+            .spacing(10)
+            .align_items(Alignment::Center)
+            .push(details)
+            .push(
+                Toggler::new(config.enabled, None, move |enabled| HomeMessage::ToggleMod(folder.clone(), enabled))
+                    .width(Length::Shrink)
+            )
+            .into()
+    }
+
+    async fn browse_for_archive() -> Option<PathBuf> {
+        let connection = zbus::Connection::session().await.ok()?;
+        let proxy = FileChooserProxy::new(&connection).await.ok()?;
+        let files = proxy.open_file(&WindowIdentifier::None, "Select a mod archive",
+                                    OpenFileOptions::default()
+                                        .accept_label("Install")
+                                        .add_filter(FileFilter::new("Mod archives")
+                                            .mimetype("application/zip")
+                                            .mimetype("application/x-7z-compressed")
+                                            .mimetype("application/vnd.rar")
+                                            .mimetype("application/x-rar")),
+        ).await.ok()?;
+        Url::parse(files.uris().first()?).ok()?.to_file_path().ok()
+    }
+
+    fn find_mod_mut(&mut self, folder: &str) -> Option<&mut ModEntry> {
+        self.catalog.as_mut().and_then(|c| c.find_mut(folder))
+    }
+}
@@ -0,0 +1,602 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use sevenz_rust::{Password, SevenZReader};
+use toml_edit::Document;
+use zip::ZipArchive;
+use crate::{constants, toml_patch, util};
+use crate::mod_conf::ModConfig;
+
+//Installer, takes a mod archive (zip, 7z or rar) and puts it in the mods folder
+//Mods are usually packed with a folder or two around them, e.g SomeMod.zip/SomeMod v1.2/SomeMod/config.toml,
+//so the folder with the config.toml in it is what gets installed, everything around it is left out.
+
+#[derive(Debug, Clone)]
+pub enum InstallErrorType {
+    CantOpenArchive(String),
+    UnsupportedArchive,
+    // RAR needs an external tool, this is the message for when none is installed
+    MissingTool(String),
+    // An entry that would end up outside of the mod folder, e.g ../../.bashrc
+    UnsafePath(String),
+    NoModConfig,
+    // More than one config.toml at the same depth, so we can't tell which one is the mod
+    MultipleMods(Vec<String>),
+    AlreadyInstalled(String),
+    Io(String),
+}
+
+impl Display for InstallErrorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InstallErrorType::CantOpenArchive(err) => write!(f, "Cannot open archive: {}", err),
+            InstallErrorType::UnsupportedArchive => write!(f, "Unsupported archive type, only zip, 7z and rar are supported"),
+            InstallErrorType::MissingTool(msg) => write!(f, "{}", msg),
+            InstallErrorType::UnsafePath(path) => write!(f, "Archive contains an unsafe path: {}", path),
+            InstallErrorType::NoModConfig => write!(f, "No {} found in the archive, is this a mod?", constants::MOD_CONFIG_FILE),
+            InstallErrorType::MultipleMods(dirs) => write!(f, "Archive contains more than one mod: {}", dirs.join(", ")),
+            InstallErrorType::AlreadyInstalled(name) => write!(f, "A mod named \"{}\" is already installed", name),
+            InstallErrorType::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InstallError(pub InstallErrorType);
+
+impl Display for InstallError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to install mod: {}", self.0)
+    }
+}
+
+impl From<io::Error> for InstallError {
+    fn from(err: io::Error) -> Self {
+        InstallError(InstallErrorType::Io(err.to_string()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InstalledMod {
+    pub folder: String,
+    pub path: PathBuf,
+}
+
+pub(crate) struct ArchiveEntry {
+    // Already checked with `safe_path`
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+pub(crate) trait ModArchive {
+    fn entries(&self) -> &[ArchiveEntry];
+
+    // Extracts every file under `root` (a directory inside the archive) into `dest`.
+    fn extract(&mut self, root: &Path, dest: &Path) -> Result<(), InstallError>;
+}
+
+//region Zip
+struct ZipModArchive {
+    archive: ZipArchive<File>,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl ZipModArchive {
+    fn open(path: &Path) -> Result<Self, InstallError> {
+        let file = File::open(path)?;
+        let mut archive = ZipArchive::new(file)
+            .map_err(|e| InstallError(InstallErrorType::CantOpenArchive(e.to_string())))?;
+
+        let mut entries = Vec::with_capacity(archive.len());
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)
+                .map_err(|e| InstallError(InstallErrorType::CantOpenArchive(e.to_string())))?;
+            entries.push(ArchiveEntry {
+                path: safe_path(entry.name())?,
+                is_dir: entry.is_dir(),
+            });
+        }
+
+        Ok(Self { archive, entries })
+    }
+}
+
+impl ModArchive for ZipModArchive {
+    fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    fn extract(&mut self, root: &Path, dest: &Path) -> Result<(), InstallError> {
+        for i in 0..self.entries.len() {
+            let entry = &self.entries[i];
+            let target = match entry.path.strip_prefix(root) {
+                Ok(rel) => dest.join(rel),
+                Err(_) => continue,
+            };
+
+            if entry.is_dir {
+                fs::create_dir_all(&target)?;
+                continue;
+            }
+
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = self.archive.by_index(i)
+                .map_err(|e| InstallError(InstallErrorType::CantOpenArchive(e.to_string())))?;
+            let mut out = File::create(&target)?;
+            io::copy(&mut file, &mut out)?;
+        }
+        Ok(())
+    }
+}
+//endregion
+
+//region 7z
+struct SevenZModArchive {
+    reader: SevenZReader<File>,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl SevenZModArchive {
+    fn open(path: &Path) -> Result<Self, InstallError> {
+        let reader = SevenZReader::open(path, Password::empty())
+            .map_err(|e| InstallError(InstallErrorType::CantOpenArchive(e.to_string())))?;
+
+        let mut entries = Vec::with_capacity(reader.archive().files.len());
+        for entry in &reader.archive().files {
+            entries.push(ArchiveEntry {
+                path: safe_path(entry.name())?,
+                is_dir: entry.is_directory(),
+            });
+        }
+
+        Ok(Self { reader, entries })
+    }
+
+    fn write_entry(data: &mut dyn Read, target: &Path) -> io::Result<()> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = File::create(target)?;
+        io::copy(data, &mut out)?;
+        Ok(())
+    }
+}
+
+impl ModArchive for SevenZModArchive {
+    fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    fn extract(&mut self, root: &Path, dest: &Path) -> Result<(), InstallError> {
+        let mut io_error = None;
+        let res = self.reader.for_each_entries(|entry, data| {
+            //7z archives are usually solid, so entries we skip still have to be read through
+            let target = match safe_path(entry.name()) {
+                Ok(path) => path.strip_prefix(root).ok().map(|rel| dest.join(rel)),
+                Err(_) => None,
+            };
+            let target = match target {
+                Some(target) => target,
+                None => {
+                    io::copy(data, &mut io::sink())?;
+                    return Ok(true);
+                }
+            };
+
+            let res = if entry.is_directory() {
+                fs::create_dir_all(&target)
+            } else {
+                Self::write_entry(data, &target)
+            };
+            if let Err(err) = res {
+                io_error = Some(err);
+                return Ok(false);
+            }
+            Ok(true)
+        });
+
+        if let Some(err) = io_error {
+            return Err(err.into());
+        }
+        res.map_err(|e| InstallError(InstallErrorType::CantOpenArchive(e.to_string())))
+    }
+}
+//endregion
+
+//region Rar
+//There is no RAR decoder we can use from Rust, so RAR goes through unrar,
+//or bsdtar (libarchive) which most distros already ship.
+#[derive(Clone, Copy)]
+enum RarTool {
+    Unrar,
+    Bsdtar,
+}
+
+impl RarTool {
+    fn find() -> Option<RarTool> {
+        let works = |cmd: &str| process::Command::new(cmd)
+            .arg("--version")
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .status()
+            .is_ok();
+
+        if works("unrar") {
+            Some(RarTool::Unrar)
+        } else if works("bsdtar") {
+            Some(RarTool::Bsdtar)
+        } else {
+            None
+        }
+    }
+
+    fn list(&self, archive: &Path) -> io::Result<process::Output> {
+        match self {
+            RarTool::Unrar => process::Command::new("unrar").arg("lb").arg("-p-").arg(archive).output(),
+            RarTool::Bsdtar => process::Command::new("bsdtar").arg("-tf").arg(archive).output(),
+        }
+    }
+
+    fn extract_all(&self, archive: &Path, dest: &Path) -> io::Result<process::Output> {
+        match self {
+            RarTool::Unrar => process::Command::new("unrar")
+                .args(["x", "-p-", "-o+", "-y", "-idq"])
+                .arg(archive)
+                //unrar wants the trailing slash to treat it as a folder
+                .arg(format!("{}/", dest.display()))
+                .output(),
+            RarTool::Bsdtar => process::Command::new("bsdtar")
+                .arg("-xf").arg(archive)
+                .arg("-C").arg(dest)
+                .output(),
+        }
+    }
+}
+
+struct RarModArchive {
+    path: PathBuf,
+    tool: RarTool,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl RarModArchive {
+    fn open(path: &Path) -> Result<Self, InstallError> {
+        let tool = RarTool::find().ok_or_else(|| InstallError(InstallErrorType::MissingTool(
+            String::from("Installing RAR archives needs unrar or bsdtar, please install one of them"))))?;
+
+        let output = tool.list(path)?;
+        if !output.status.success() {
+            let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(InstallError(InstallErrorType::CantOpenArchive(err)));
+        }
+
+        let mut entries = Vec::new();
+        for name in String::from_utf8_lossy(&output.stdout).lines() {
+            if name.is_empty() {
+                continue;
+            }
+            entries.push(ArchiveEntry {
+                path: safe_path(name)?,
+                is_dir: name.ends_with('/'),
+            });
+        }
+
+        Ok(Self { path: path.to_path_buf(), tool, entries })
+    }
+}
+
+impl ModArchive for RarModArchive {
+    fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    fn extract(&mut self, root: &Path, dest: &Path) -> Result<(), InstallError> {
+        //The tools can't extract just a folder without keeping the path in front of it,
+        //so everything goes into a temporary folder and the mod is moved out of it.
+        let unpack = util::append_to_name(dest, ".unpack");
+        if unpack.exists() {
+            fs::remove_dir_all(&unpack)?;
+        }
+        fs::create_dir_all(&unpack)?;
+
+        let res = self.tool.extract_all(&self.path, &unpack)
+            .map_err(InstallError::from)
+            .and_then(|output| {
+                if output.status.success() {
+                    return Ok(());
+                }
+                let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                Err(InstallError(InstallErrorType::CantOpenArchive(err)))
+            })
+            .and_then(|_| reject_symlinks(&unpack, &unpack))
+            .and_then(|_| fs::rename(unpack.join(root), dest).map_err(InstallError::from));
+
+        let _ = fs::remove_dir_all(&unpack);
+        res
+    }
+}
+
+// The entry names were checked by `safe_path`, but a symlink entry can still point anywhere,
+// and the loader would follow it, so a mod with links in it isn't installed.
+fn reject_symlinks(dir: &Path, unpack: &Path) -> Result<(), InstallError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_type = fs::symlink_metadata(&path)?.file_type();
+        if file_type.is_symlink() {
+            let name = path.strip_prefix(unpack).unwrap_or(&path).display().to_string();
+            return Err(InstallError(InstallErrorType::UnsafePath(name)));
+        }
+        if file_type.is_dir() {
+            reject_symlinks(&path, unpack)?;
+        }
+    }
+    Ok(())
+}
+//endregion
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    SevenZ,
+    Rar,
+}
+
+impl ArchiveKind {
+    // Works out the archive type from the first bytes of the file,
+    // mods get shared with the wrong extension more often than you'd think.
+    pub fn detect(path: &Path) -> Result<ArchiveKind, InstallError> {
+        let mut magic = [0u8; 8];
+        let mut file = File::open(path)?;
+        let read = file.read(&mut magic)?;
+        let magic = &magic[..read];
+
+        if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") || magic.starts_with(b"PK\x07\x08") {
+            Ok(ArchiveKind::Zip)
+        } else if magic.starts_with(b"7z\xBC\xAF\x27\x1C") {
+            Ok(ArchiveKind::SevenZ)
+        } else if magic.starts_with(b"Rar!\x1A\x07") {
+            Ok(ArchiveKind::Rar)
+        } else {
+            Err(InstallError(InstallErrorType::UnsupportedArchive))
+        }
+    }
+}
+
+pub(crate) fn open_archive(path: &Path) -> Result<Box<dyn ModArchive>, InstallError> {
+    Ok(match ArchiveKind::detect(path)? {
+        ArchiveKind::Zip => Box::new(ZipModArchive::open(path)?),
+        ArchiveKind::SevenZ => Box::new(SevenZModArchive::open(path)?),
+        ArchiveKind::Rar => Box::new(RarModArchive::open(path)?),
+    })
+}
+
+// Turns an archive entry name into a relative path,
+// anything absolute or with a ".." in it is refused.
+pub(crate) fn safe_path(name: &str) -> Result<PathBuf, InstallError> {
+    //Archives made on Windows can use \ as the separator
+    let name = name.replace('\\', "/");
+    let mut path = PathBuf::new();
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(part) => {
+                //Drive letters, e.g C:/Windows
+                let bytes = part.as_encoded_bytes();
+                if path.as_os_str().is_empty() && bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic() {
+                    return Err(InstallError(InstallErrorType::UnsafePath(name)));
+                }
+                path.push(part)
+            }
+            Component::CurDir => {}
+            _ => return Err(InstallError(InstallErrorType::UnsafePath(name))),
+        }
+    }
+    Ok(path)
+}
+
+// Finds the directory in the archive that holds the mod's config.toml,
+// the one closest to the top wins.
+pub(crate) fn find_mod_root(entries: &[ArchiveEntry]) -> Result<PathBuf, InstallError> {
+    let configs: Vec<&Path> = entries.iter()
+        .filter(|e| !e.is_dir)
+        .filter(|e| e.path.file_name()
+            .map(|n| n.eq_ignore_ascii_case(constants::MOD_CONFIG_FILE))
+            .unwrap_or(false))
+        .map(|e| e.path.parent().unwrap_or_else(|| Path::new("")))
+        .collect();
+
+    let depth = match configs.iter().map(|p| p.components().count()).min() {
+        Some(depth) => depth,
+        None => return Err(InstallError(InstallErrorType::NoModConfig)),
+    };
+
+    let roots: Vec<&Path> = configs.into_iter()
+        .filter(|p| p.components().count() == depth)
+        .collect();
+    if roots.len() > 1 {
+        let names = roots.iter().map(|p| p.display().to_string()).collect();
+        return Err(InstallError(InstallErrorType::MultipleMods(names)));
+    }
+
+    Ok(roots[0].to_path_buf())
+}
+
+// Makes a name safe to use as a folder on both Linux and Windows (the game runs through Proton).
+pub fn sanitize_folder_name(name: &str) -> String {
+    let cleaned: String = name.chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+
+    if cleaned.is_empty() {
+        return String::from("mod");
+    }
+    cleaned.to_string()
+}
+
+fn folder_name_for(archive_path: &Path, root: &Path) -> String {
+    let name = match root.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        //config.toml is at the top of the archive, so the archive name is all we have
+        None => archive_path.file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+    sanitize_folder_name(&name)
+}
+
+// What to do when the mod folder is already in the mods folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictAction {
+    // Throw away the installed mod and put the new one in its place
+    Overwrite,
+    // Install the new one next to it, as "Name (2)"
+    KeepBoth,
+    // Replace the files, but keep `enabled` and the settings the user changed in config.toml
+    Upgrade,
+}
+
+// Installs the mod in `archive_path` into `mods_dir`.
+// It's extracted into a folder next to the mods folder first and renamed when done,
+// so a failed install never leaves a half extracted mod behind.
+// Staging outside of the mods folder means a crash mid install can't leave anything the loader would pick up.
+// Without `on_conflict` an existing mod folder fails with `AlreadyInstalled`, so the caller can ask the user.
+pub fn install_archive(archive_path: &Path, mods_dir: &Path, on_conflict: Option<ConflictAction>) -> Result<InstalledMod, InstallError> {
+    let mut archive = open_archive(archive_path)?;
+    let root = find_mod_root(archive.entries())?;
+    let mut folder = folder_name_for(archive_path, &root);
+
+    let mut target = mods_dir.join(&folder);
+    let existing = target.exists();
+    if existing {
+        match on_conflict {
+            None => return Err(InstallError(InstallErrorType::AlreadyInstalled(folder))),
+            Some(ConflictAction::KeepBoth) => {
+                folder = free_folder_name(mods_dir, &folder);
+                target = mods_dir.join(&folder);
+            }
+            Some(_) => {}
+        }
+    }
+
+    fs::create_dir_all(mods_dir)?;
+    let staging_dir = staging_dir(mods_dir);
+    fs::create_dir_all(&staging_dir)?;
+    let staging = staging_dir.join(format!("install-{}", folder));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    let res = archive.extract(&root, &staging).and_then(|_| {
+        normalize_config_name(&staging)?;
+        if existing && on_conflict == Some(ConflictAction::Upgrade) {
+            keep_user_config(&target, &staging)?;
+        }
+        replace_folder(&staging, &target, &staging_dir)
+    });
+    if res.is_err() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    //Only goes if nothing else is installing right now
+    let _ = fs::remove_dir(&staging_dir);
+    res?;
+
+    Ok(InstalledMod { folder, path: target })
+}
+
+// Where mods are unpacked before they're moved into `mods_dir`.
+// Next to it rather than in it, but on the same drive so moving them is just a rename.
+fn staging_dir(mods_dir: &Path) -> PathBuf {
+    match mods_dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.join(constants::STAGING_DIR),
+        _ => PathBuf::from(constants::STAGING_DIR),
+    }
+}
+
+pub async fn install(archive_path: PathBuf, mods_dir: PathBuf, on_conflict: Option<ConflictAction>) -> Result<InstalledMod, InstallError> {
+    util::run_blocking(move || install_archive(&archive_path, &mods_dir, on_conflict)).await
+}
+
+// "Name (2)", "Name (3)"... whichever is free first.
+fn free_folder_name(mods_dir: &Path, folder: &str) -> String {
+    let mut n = 2;
+    loop {
+        let name = format!("{} ({})", folder, n);
+        if !mods_dir.join(&name).exists() {
+            return name;
+        }
+        n += 1;
+    }
+}
+
+// Moves the freshly extracted mod into place.
+// The old folder is only deleted once the new one is in, so there is always a working copy.
+fn replace_folder(staging: &Path, target: &Path, staging_dir: &Path) -> Result<(), InstallError> {
+    if !target.exists() {
+        fs::rename(staging, target)?;
+        return Ok(());
+    }
+
+    let name = target.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let old = staging_dir.join(format!("old-{}", name));
+    if old.exists() {
+        fs::remove_dir_all(&old)?;
+    }
+
+    fs::rename(target, &old)?;
+    if let Err(err) = fs::rename(staging, target) {
+        let _ = fs::rename(&old, target);
+        return Err(err.into());
+    }
+    fs::remove_dir_all(&old)?;
+    Ok(())
+}
+
+// find_mod_root takes Config.toml or CONFIG.TOML too, the loader reads those fine through Proton.
+// Everything else in M4 opens config.toml, so the mod gets it under that name.
+fn normalize_config_name(staging: &Path) -> io::Result<()> {
+    if staging.join(constants::MOD_CONFIG_FILE).is_file() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(staging)? {
+        let path = entry?.path();
+        if path.is_file() && path.file_name().is_some_and(|n| n.eq_ignore_ascii_case(constants::MOD_CONFIG_FILE)) {
+            return fs::rename(&path, staging.join(constants::MOD_CONFIG_FILE));
+        }
+    }
+    Ok(())
+}
+
+// Copies `enabled` and the mod's own settings from the installed config.toml into the new one.
+// The mod's description (name, version, include...) always comes from the new version.
+fn keep_user_config(installed: &Path, staging: &Path) -> Result<(), InstallError> {
+    let old_path = installed.join(constants::MOD_CONFIG_FILE);
+    let new_path = staging.join(constants::MOD_CONFIG_FILE);
+    if !old_path.is_file() || !new_path.is_file() {
+        return Ok(());
+    }
+
+    let old = fs::read_to_string(&old_path)?;
+    let old = match old.parse::<Document>() {
+        Ok(doc) => doc,
+        //Nothing to keep from a broken config
+        Err(_) => return Ok(()),
+    };
+
+    //Still in staging, so no backup, it would end up in the installed mod
+    let new = fs::read_to_string(&new_path)?;
+    let patched = toml_patch::patch_str(&new, |doc| toml_patch::carry_over_values(doc, &old, ModConfig::METADATA_KEYS))
+        .map_err(|e| InstallError(InstallErrorType::Io(e)))?;
+    fs::write(&new_path, patched)?;
+    Ok(())
+}
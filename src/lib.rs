@@ -5,5 +5,25 @@ pub mod m4_settings;
 pub mod constants;
 pub mod util;
 pub mod mod_conf;
+pub mod mod_catalog;
+pub mod file_index;
+pub mod conflicts;
+pub mod pv_db;
+pub mod farc;
+pub mod loader_conf;
+pub mod installer;
+pub mod loader_installer;
+pub mod loader_update;
+pub mod cli;
+pub mod toml_patch;
 pub mod fts;
+pub mod home;
+pub mod error_screen;
+pub mod priority;
+pub mod conflict_list;
+pub mod file_tree;
+pub mod profile;
+pub mod manifest;
+pub mod profile_list;
+pub mod steam;
 mod style;
@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_derive::{Deserialize, Serialize};
+use toml_edit::{Array, Value};
+use crate::constants;
+use crate::m4_settings::LoadError;
+use crate::{toml_patch, util};
+
+//DivaModLoader's own config, the config.toml next to dinput8.dll.
+//Every key is optional for the loader, so missing keys fall back to what the loader would use.
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LoaderConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub console: bool,
+    // Name of the mods folder, relative to the game directory
+    #[serde(default = "default_mods")]
+    pub mods: String,
+    // Mod folder names, earlier entries win over later ones
+    #[serde(default)]
+    pub priority: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_mods() -> String {
+    String::from(constants::MODS_DIR)
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            console: false,
+            mods: default_mods(),
+            priority: Vec::new(),
+        }
+    }
+}
+
+impl LoaderConfig {
+    pub fn path(game_dir: &Path) -> PathBuf {
+        game_dir.join(constants::LOADER_CONFIG_FILE)
+    }
+
+    // Reads the loader config of a game directory.
+    // No config is fine, the loader runs with its defaults then.
+    pub fn load(game_dir: &Path) -> Result<LoaderConfig, LoadError> {
+        let path = Self::path(game_dir);
+        if !path.exists() {
+            return Ok(LoaderConfig::default());
+        }
+
+        let buffer = fs::read_to_string(&path).map_err(|e| LoadError::read(e, &path))?;
+        toml::from_str(&buffer).map_err(|e| LoadError::parse(e, &path))
+    }
+
+    // Where the loader will look for mods.
+    pub fn mods_dir(&self, game_dir: &Path) -> PathBuf {
+        if self.mods.trim().is_empty() {
+            return game_dir.join(constants::MODS_DIR);
+        }
+        game_dir.join(&self.mods)
+    }
+
+    // Writes the fields that differ from what's on disk,
+    // comments and anything else in the file are kept.
+    pub fn save(&self, game_dir: &Path) -> Result<(), String> {
+        let path = Self::path(game_dir);
+        if !path.exists() {
+            let data = toml::to_string(self).map_err(|e| e.to_string())?;
+            return util::write_atomic(&path, data.as_bytes()).map_err(|e| e.to_string());
+        }
+
+        let current = Self::load(game_dir).map_err(|e| e.to_string())?;
+        toml_patch::patch_file(&path, |doc| {
+            if current.enabled != self.enabled {
+                toml_patch::set_value(doc, "enabled", self.enabled);
+            }
+            if current.console != self.console {
+                toml_patch::set_value(doc, "console", self.console);
+            }
+            if current.mods != self.mods {
+                toml_patch::set_value(doc, "mods", self.mods.as_str());
+            }
+            if current.priority != self.priority {
+                toml_patch::set_value(doc, "priority", Value::Array(priority_array(&self.priority)));
+            }
+        })
+    }
+
+    pub async fn save_enabled(game_dir: PathBuf, enabled: bool) -> Result<bool, String> {
+        util::run_blocking(move || {
+            let mut config = Self::load(&game_dir).map_err(|e| e.to_string())?;
+            config.enabled = enabled;
+            config.save(&game_dir)?;
+            Ok(enabled)
+        }).await
+    }
+
+    pub async fn save_priority(game_dir: PathBuf, priority: Vec<String>) -> Result<Vec<String>, String> {
+        util::run_blocking(move || {
+            let mut config = Self::load(&game_dir).map_err(|e| e.to_string())?;
+            config.priority = priority.clone();
+            config.save(&game_dir)?;
+            Ok(priority)
+        }).await
+    }
+
+    // The load order of the installed mods.
+    // Mods the priority list doesn't mention go last, sorted by folder name
+    // so every run of M4 comes up with the same order.
+    pub fn ordered_mods(&self, installed: &[String]) -> Vec<String> {
+        let mut ordered: Vec<String> = Vec::new();
+        for name in &self.priority {
+            if installed.contains(name) && !ordered.contains(name) {
+                ordered.push(name.clone());
+            }
+        }
+
+        let mut missing: Vec<String> = installed.iter()
+            .filter(|name| !ordered.contains(name))
+            .cloned()
+            .collect();
+        missing.sort();
+        ordered.extend(missing);
+        ordered
+    }
+
+    // The priority list to save for a new order of the installed mods.
+    // Entries of mods that aren't installed anymore are kept at the end.
+    pub fn priority_for(&self, order: &[String]) -> Vec<String> {
+        let mut priority = order.to_vec();
+        for name in &self.priority {
+            if !priority.contains(name) {
+                priority.push(name.clone());
+            }
+        }
+        priority
+    }
+}
+
+fn priority_array(priority: &[String]) -> Array {
+    priority.iter().map(|s| s.as_str()).collect()
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde_derive::{Deserialize, Serialize};
+use toml_edit::Document;
+use crate::{constants, toml_patch, util};
+use crate::installer;
+use crate::installer::InstallError;
+use crate::loader_conf::LoaderConfig;
+
+//Loader installer, puts DivaModLoader into the game directory from one of its release archives
+//Releases are dinput8.dll and a default config.toml, the dll is replaced
+//but an existing config is kept as is (priority, mods folder...), it only gets the keys the new release added.
+//The version we installed is written down in m4_loader.toml since the dll doesn't say.
+
+#[derive(Debug, Clone)]
+pub enum LoaderInstallErrorType {
+    CantOpenArchive(String),
+    NoLoaderDll,
+    BadConfig(String),
+    Io(String),
+}
+
+impl Display for LoaderInstallErrorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoaderInstallErrorType::CantOpenArchive(err) => write!(f, "{}", err),
+            LoaderInstallErrorType::NoLoaderDll => write!(f, "No {} found in the archive, is this a DivaModLoader release?", constants::LOADER_DLL),
+            LoaderInstallErrorType::BadConfig(err) => write!(f, "Cannot update {}: {}", constants::LOADER_CONFIG_FILE, err),
+            LoaderInstallErrorType::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoaderInstallError(pub LoaderInstallErrorType);
+
+impl Display for LoaderInstallError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to install DivaModLoader: {}", self.0)
+    }
+}
+
+impl From<io::Error> for LoaderInstallError {
+    fn from(err: io::Error) -> Self {
+        LoaderInstallError(LoaderInstallErrorType::Io(err.to_string()))
+    }
+}
+
+impl From<InstallError> for LoaderInstallError {
+    fn from(err: InstallError) -> Self {
+        LoaderInstallError(LoaderInstallErrorType::CantOpenArchive(err.0.to_string()))
+    }
+}
+
+// What m4_loader.toml holds.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LoaderRecord {
+    // None when the archive didn't tell us
+    pub version: Option<String>,
+}
+
+impl LoaderRecord {
+    pub fn path(game_dir: &Path) -> PathBuf {
+        game_dir.join(constants::LOADER_RECORD_FILE)
+    }
+
+    // The record of the last install, None if M4 never installed the loader here.
+    pub fn load(game_dir: &Path) -> Option<LoaderRecord> {
+        let buffer = fs::read_to_string(Self::path(game_dir)).ok()?;
+        toml::from_str(&buffer).ok()
+    }
+
+    pub fn save(&self, game_dir: &Path) -> io::Result<()> {
+        let data = toml::to_string(self).map_err(|e| io::Error::other(e.to_string()))?;
+        util::write_atomic(&Self::path(game_dir), data.as_bytes())
+    }
+}
+
+// The version of the loader M4 installed, if the loader is there at all.
+pub fn installed_version(game_dir: &Path) -> Option<String> {
+    if !game_dir.join(constants::LOADER_DLL).is_file() {
+        return None;
+    }
+    LoaderRecord::load(game_dir)?.version
+}
+
+// Release archives are named like DivaModLoader-v1.0.2.zip, the version is the first
+// run of digits and dots after a "v" or a separator.
+pub fn version_from_name(name: &str) -> Option<String> {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, ext)| match ext.parse::<u32>() {
+        //1.0.2 with no extension
+        Ok(_) => name,
+        Err(_) => stem,
+    });
+    let bytes = stem.as_bytes();
+    for (i, c) in stem.char_indices() {
+        let starts = c.is_ascii_digit() && (i == 0 || matches!(bytes[i - 1], b'v' | b'V' | b'-' | b'_' | b' '));
+        if !starts {
+            continue;
+        }
+        let version: String = stem[i..].chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+        let version = version.trim_end_matches('.');
+        if !version.is_empty() {
+            return Some(version.to_string());
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct InstalledLoader {
+    pub version: Option<String>,
+    // The game already had a config.toml, and it was kept
+    pub kept_config: bool,
+}
+
+// Installs the loader in `archive` into `game_dir`.
+// `version` is what the caller knows about the release, e.g from the release feed,
+// without one it's taken from the archive name.
+pub fn install_loader(archive_path: &Path, game_dir: &Path, version: Option<String>) -> Result<InstalledLoader, LoaderInstallError> {
+    let mut archive = installer::open_archive(archive_path)?;
+    //The folder the dll is in, the one closest to the top if there's more than one
+    let root = archive.entries().iter()
+        .filter(|e| !e.is_dir)
+        .filter(|e| e.path.file_name().is_some_and(|n| n.eq_ignore_ascii_case(constants::LOADER_DLL)))
+        .map(|e| e.path.parent().unwrap_or_else(|| Path::new("")).to_path_buf())
+        .min_by_key(|p| p.components().count())
+        .ok_or(LoaderInstallError(LoaderInstallErrorType::NoLoaderDll))?;
+
+    let staging = game_dir.join(".m4-loader-install");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    let res = archive.extract(&root, &staging)
+        .map_err(LoaderInstallError::from)
+        .and_then(|_| place_files(&staging, game_dir));
+    let _ = fs::remove_dir_all(&staging);
+    let kept_config = res?;
+
+    let version = version.or_else(|| version_from_name(&archive_path.file_name()?.to_string_lossy()));
+    LoaderRecord { version: version.clone() }.save(game_dir)?;
+    Ok(InstalledLoader { version, kept_config })
+}
+
+pub async fn install(archive_path: PathBuf, game_dir: PathBuf, version: Option<String>) -> Result<InstalledLoader, LoaderInstallError> {
+    util::run_blocking(move || install_loader(&archive_path, &game_dir, version)).await
+}
+
+// Moves the dll and config out of the extracted release, returns whether the old config was kept.
+fn place_files(staging: &Path, game_dir: &Path) -> Result<bool, LoaderInstallError> {
+    let dll = find_file(staging, constants::LOADER_DLL).ok_or(LoaderInstallError(LoaderInstallErrorType::NoLoaderDll))?;
+    let release_config = find_file(staging, constants::LOADER_CONFIG_FILE);
+
+    //Config first, a new dll with a config it can't read is worse than an old dll
+    let config_path = LoaderConfig::path(game_dir);
+    let kept_config = config_path.is_file();
+    match (&release_config, kept_config) {
+        (Some(release_config), true) => {
+            let release = fs::read_to_string(release_config)?
+                .parse::<Document>()
+                .map_err(|e| LoaderInstallError(LoaderInstallErrorType::BadConfig(e.to_string())))?;
+            toml_patch::patch_file(&config_path, |doc| toml_patch::add_missing_keys(doc, &release))
+                .map_err(|e| LoaderInstallError(LoaderInstallErrorType::BadConfig(e)))?;
+        }
+        (Some(release_config), false) => util::write_atomic(&config_path, &fs::read(release_config)?)?,
+        (None, true) => {}
+        (None, false) => LoaderConfig::default().save(game_dir)
+            .map_err(|e| LoaderInstallError(LoaderInstallErrorType::BadConfig(e)))?,
+    }
+
+    let dll_path = game_dir.join(constants::LOADER_DLL);
+    util::write_atomic_with(&dll_path, |out| io::copy(&mut File::open(&dll)?, out).map(|_| ()))?;
+    Ok(kept_config)
+}
+
+// `name` right inside `dir`, compared without case since releases are made on Windows.
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(dir).ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.is_file() && p.file_name().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+}
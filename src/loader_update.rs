@@ -0,0 +1,264 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde_derive::Deserialize;
+use url::Url;
+use crate::constants;
+use crate::loader_conf::LoaderConfig;
+use crate::loader_installer;
+use crate::loader_installer::{InstalledLoader, LoaderInstallError, LoaderRecord};
+use crate::util;
+
+//Loader updates, DivaModLoader releases from a release feed
+//The feed is anything that answers like GitHub's releases API, either the list (/releases)
+//or a single release (/releases/latest). It can be a file:// URL, so can the downloads,
+//which is how a local mirror works without a web server.
+//An update that fails halfway puts the old dll, config and version record back.
+
+#[derive(Debug, Clone)]
+pub enum UpdateErrorType {
+    Feed(String),
+    // The feed has no release we can use
+    NoRelease,
+    // The latest release has no zip to download, holds the tag
+    NoArchive(String),
+    Download(String),
+    Install(LoaderInstallError),
+    // The install failed and so did putting the old files back
+    RollbackFailed(LoaderInstallError, String),
+}
+
+impl Display for UpdateErrorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateErrorType::Feed(err) => write!(f, "Cannot read the release feed: {}", err),
+            UpdateErrorType::NoRelease => write!(f, "The release feed has no releases"),
+            UpdateErrorType::NoArchive(tag) => write!(f, "Release {} has no zip to download", tag),
+            UpdateErrorType::Download(err) => write!(f, "Download failed: {}", err),
+            UpdateErrorType::Install(err) => write!(f, "{}, the previous version was put back", err),
+            UpdateErrorType::RollbackFailed(err, rollback) => {
+                write!(f, "{}, and the previous version could not be put back: {}", err, rollback)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateError(pub UpdateErrorType);
+
+impl Display for UpdateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// The parts of a GitHub release we use.
+#[derive(Debug, Deserialize)]
+struct FeedRelease {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    assets: Vec<FeedAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Feed {
+    List(Vec<FeedRelease>),
+    Single(FeedRelease),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    // The tag without the leading v, e.g 1.0.2
+    pub version: String,
+    pub tag: String,
+    pub archive_name: String,
+    pub download_url: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateCheck {
+    // None if the loader isn't installed, or M4 didn't install it and can't tell
+    pub installed: Option<String>,
+    pub latest: Release,
+}
+
+impl UpdateCheck {
+    // An unknown installed version counts as outdated, updating is how it becomes known.
+    pub fn update_available(&self) -> bool {
+        match &self.installed {
+            Some(installed) => compare_versions(installed, &self.latest.version) == Ordering::Less,
+            None => true,
+        }
+    }
+}
+
+// Compares dotted versions number by number, 1.10 is newer than 1.9.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<u64> {
+        v.trim_start_matches(['v', 'V'])
+            .split('.')
+            .map(|p| p.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+    for i in 0..a.len().max(b.len()) {
+        match a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)) {
+            Ordering::Equal => continue,
+            other => return other,
+        }
+    }
+    Ordering::Equal
+}
+
+// Opens an http(s) or file URL for reading.
+fn open_url(url: &str) -> Result<Box<dyn Read + Send>, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("{}: {}", url, e))?;
+    if parsed.scheme() == "file" {
+        let path = parsed.to_file_path().map_err(|_| format!("{} is not a local path", url))?;
+        return File::open(&path)
+            .map(|f| Box::new(f) as Box<dyn Read + Send>)
+            .map_err(|e| format!("{}: {}", path.display(), e));
+    }
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(15))
+        .timeout_read(Duration::from_secs(60))
+        .user_agent(&format!("{}/{}", constants::APPNAME, env!("CARGO_PKG_VERSION")))
+        .build();
+    let response = agent.get(url).call().map_err(|e| e.to_string())?;
+    Ok(response.into_reader())
+}
+
+// The newest release in the feed at `feed_url` that isn't a draft or a pre-release.
+pub fn fetch_latest(feed_url: &str) -> Result<Release, UpdateError> {
+    let feed_error = |e: String| UpdateError(UpdateErrorType::Feed(e));
+    let mut body = String::new();
+    open_url(feed_url).map_err(feed_error)?
+        .read_to_string(&mut body)
+        .map_err(|e| feed_error(e.to_string()))?;
+    let feed: Feed = serde_json::from_str(&body).map_err(|e| feed_error(e.to_string()))?;
+
+    //GitHub lists the newest release first
+    let release = match feed {
+        Feed::List(releases) => releases.into_iter().find(|r| !r.draft && !r.prerelease),
+        Feed::Single(release) => Some(release),
+    }.ok_or(UpdateError(UpdateErrorType::NoRelease))?;
+
+    //Releases only have the one zip, but prefer the one with the loader's name in case that changes
+    let asset = release.assets.iter()
+        .filter(|a| a.name.to_lowercase().ends_with(".zip"))
+        .max_by_key(|a| a.name.to_lowercase().contains("divamodloader"))
+        .ok_or_else(|| UpdateError(UpdateErrorType::NoArchive(release.tag_name.clone())))?;
+
+    Ok(Release {
+        version: release.tag_name.trim_start_matches(['v', 'V']).to_string(),
+        tag: release.tag_name.clone(),
+        archive_name: asset.name.clone(),
+        download_url: asset.browser_download_url.clone(),
+    })
+}
+
+pub fn check(game_dir: &Path, feed_url: &str) -> Result<UpdateCheck, UpdateError> {
+    let latest = fetch_latest(feed_url)?;
+    Ok(UpdateCheck { installed: loader_installer::installed_version(game_dir), latest })
+}
+
+pub async fn check_for_update(game_dir: PathBuf, feed_url: String) -> Result<UpdateCheck, UpdateError> {
+    util::run_blocking(move || check(&game_dir, &feed_url)).await
+}
+
+// Downloads the release archive into `dir`, the caller removes it when done.
+pub fn download(release: &Release, dir: &Path) -> Result<PathBuf, UpdateError> {
+    let download_error = |e: String| UpdateError(UpdateErrorType::Download(e));
+    let path = dir.join(format!(".m4-download-{}", release.archive_name.replace(['/', '\\'], "_")));
+    //Through a temp name, a download cut short never sits where the install looks for it
+    let res = open_url(&release.download_url).and_then(|mut reader| {
+        util::write_atomic_no_backup_with(&path, |file| io::copy(&mut reader, file).map(|_| ())).map_err(|e| e.to_string())
+    });
+    if let Err(err) = res {
+        let _ = fs::remove_file(&path);
+        return Err(download_error(err));
+    }
+    Ok(path)
+}
+
+// The files an install touches, as they were before it.
+struct Snapshot {
+    files: Vec<(PathBuf, Option<Vec<u8>>)>,
+}
+
+impl Snapshot {
+    fn take(game_dir: &Path) -> io::Result<Snapshot> {
+        let paths = [
+            game_dir.join(constants::LOADER_DLL),
+            LoaderConfig::path(game_dir),
+            LoaderRecord::path(game_dir),
+        ];
+        let mut files = Vec::new();
+        for path in paths {
+            let contents = match fs::read(&path) {
+                Ok(contents) => Some(contents),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err),
+            };
+            files.push((path, contents));
+        }
+        Ok(Snapshot { files })
+    }
+
+    fn restore(&self) -> io::Result<()> {
+        for (path, contents) in &self.files {
+            match contents {
+                //Only what the install got to, no point rewriting the rest
+                Some(contents) if fs::read(path).ok().as_ref() == Some(contents) => {}
+                //A .bak of the half installed file would only be left lying around in the game folder
+                Some(contents) => util::write_atomic_no_backup(path, contents)?,
+                None if path.exists() => fs::remove_file(path)?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// Installs `archive` as `version`, putting the old loader back if that fails.
+pub fn install_with_rollback(archive: &Path, game_dir: &Path, version: Option<String>) -> Result<InstalledLoader, UpdateError> {
+    let snapshot = Snapshot::take(game_dir)
+        .map_err(|e| UpdateError(UpdateErrorType::Install(e.into())))?;
+    match loader_installer::install_loader(archive, game_dir, version) {
+        Ok(installed) => Ok(installed),
+        Err(err) => match snapshot.restore() {
+            Ok(()) => Err(UpdateError(UpdateErrorType::Install(err))),
+            Err(rollback) => Err(UpdateError(UpdateErrorType::RollbackFailed(err, rollback.to_string()))),
+        },
+    }
+}
+
+// Downloads and installs `release` into `game_dir`.
+pub fn update(game_dir: &Path, release: &Release) -> Result<InstalledLoader, UpdateError> {
+    let archive = download(release, game_dir)?;
+    let res = install_with_rollback(&archive, game_dir, Some(release.version.clone()));
+    let _ = fs::remove_file(&archive);
+    res
+}
+
+pub async fn update_loader(game_dir: PathBuf, release: Release) -> Result<InstalledLoader, UpdateError> {
+    util::run_blocking(move || update(&game_dir, &release)).await
+}
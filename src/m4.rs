@@ -1,16 +1,18 @@
 use std::path::Path;
-use iced::{Application, Command, Container, Element, executor, Length, Text};
-use crate::{constants, fts, util};
-use crate::fts::FirstTimeSetup;
+use iced::{Application, Command, Container, Element, executor, Length, Subscription, Text};
+use crate::{constants, error_screen, fts, home, util};
+use crate::error_screen::{ErrorMessage, ErrorScreen};
+use crate::fts::{FirstTimeSetup, FTSMessage};
+use crate::home::{Home, HomeMessage};
 use crate::m4_settings::{LoadError, LoadErrorType, M4Settings};
-use crate::util::{is_valid_megamix_dir, load_file_into};
+use crate::util::check_megamix_dir;
 
 #[derive(PartialEq)]
 pub enum M4State {
     // Shows a loading screen
     LoadingScreen,
-    // Shows an error screen
-    ErrorScreen(String),
+    // Shows an error screen, the error is in `M4::error_screen`
+    ErrorScreen,
     // If settings cant be loaded, or if they are not set up
     FirstTimeSetup,
     // Main screen
@@ -21,14 +23,44 @@ pub struct M4 {
     state: M4State,
     settings: M4Settings,
     fts: FirstTimeSetup,
+    home: Home,
+    error_screen: ErrorScreen,
 }
 
-impl M4 {}
+impl M4 {
+    fn load_settings() -> Command<Message> {
+        match util::get_config_path() {
+            Some(path) => Command::perform(M4Settings::load(path), Message::LoadedSettings),
+            //Goes through LoadedSettings like any other error, so it ends up on the error screen
+            None => Command::perform(async { Err(LoadError::no_config_dir()) }, Message::LoadedSettings),
+        }
+    }
+
+    fn show_error(&mut self, err: LoadError) -> Command<Message> {
+        self.error_screen.set_error(err);
+        self.state = M4State::ErrorScreen;
+        Command::none()
+    }
+
+    fn go_home(&mut self) -> Command<Message> {
+        self.state = M4State::Home;
+        self.home.load(Path::new(&self.settings.game_dir)).map(Message::HomeMessage)
+    }
+
+    fn go_setup(&mut self) -> Command<Message> {
+        self.state = M4State::FirstTimeSetup;
+        self.fts.start(self.settings.loader_feed()).map(Message::FTSMessage)
+    }
+}
 
 #[derive(Debug)]
 pub enum Message {
     LoadedSettings(Result<M4Settings, LoadError>),
+    // settings.toml was written at the end of first time setup
+    SettingsSaved(Result<(), LoadError>),
     FTSMessage(fts::FTSMessage),
+    HomeMessage(home::HomeMessage),
+    ErrorMessage(error_screen::ErrorMessage),
 }
 
 impl Application for M4 {
@@ -44,11 +76,11 @@ impl Application for M4 {
                 state,
                 settings,
                 fts: FirstTimeSetup::new(),
+                home: Home::new(),
+                error_screen: ErrorScreen::new(),
             },
             // On start up, perform a command to load the settings.
-            Command::perform(
-                load_file_into::<M4Settings>(util::get_config_path().expect("Failed to get config path")),
-                Message::LoadedSettings)
+            Self::load_settings()
         )
     }
 
@@ -57,45 +89,89 @@ impl Application for M4 {
     }
 
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
-        return match message {
+        match message {
             Message::LoadedSettings(settings) => {
-                if settings.is_err() {
-                    //Check error
-                    //If error is file not found, then go to first time setup
-                    //If error is cant open file, then go to error screen
-                    return match settings.err().unwrap().0 {
-                        LoadErrorType::CantOpenFile => {
-                            self.state = M4State::ErrorScreen(String::from("Cannot open settings file!"));
-                            Command::none()
-                        }
-                        LoadErrorType::FileNotFound => {
-                            //If were here that means the settings file doesn't exist,
-                            //so the user needs to go though setup.
-                            self.state = M4State::FirstTimeSetup;
-                            Command::none()
-                        }
-                    };
-                }
+                //Check error
+                //If error is file not found, then go to first time setup
+                //Anything else goes to the error screen
+                let settings = match settings {
+                    Ok(settings) => settings,
+                    Err(err) => {
+                        return match err.kind() {
+                            LoadErrorType::FileNotFound => {
+                                //If were here that means the settings file doesn't exist,
+                                //so the user needs to go though setup.
+                                self.go_setup()
+                            }
+                            _ => self.show_error(err),
+                        };
+                    }
+                };
 
                 //If were here that means the settings file exists,
                 //so lets do a simple check for required settings for home.
-                self.settings = settings.unwrap();
+                self.settings = settings;
                 if self.settings.game_dir.is_empty() {
                     //Game dir doesn't exist.
-                    self.state = M4State::FirstTimeSetup;
-                } else if is_valid_megamix_dir(Path::new(&self.settings.game_dir)) {
+                    self.go_setup()
+                } else if check_megamix_dir(Path::new(&self.settings.game_dir)).is_ok() {
                     //The game dir exists, and is valid.
-                    self.state = M4State::Home;
+                    self.go_home()
                 } else {
                     //The game dir exists, but is invalid.
-                    self.state = M4State::FirstTimeSetup;
+                    self.go_setup()
                 }
-                Command::none()
             }
+            Message::FTSMessage(FTSMessage::FtsDone) => {
+                self.fts.apply_to(&mut self.settings);
+                let settings = self.settings.clone();
+                Command::perform(util::run_blocking(move || match util::get_config_path() {
+                    Some(path) => M4Settings::save_settings(path, &settings),
+                    None => Err(LoadError::no_config_dir()),
+                }), Message::SettingsSaved)
+            }
+            Message::SettingsSaved(Ok(())) => self.go_home(),
+            Message::SettingsSaved(Err(err)) => self.show_error(err),
             Message::FTSMessage(msg) => {
-                self.fts.update(msg).map(|cmd| Message::FTSMessage(cmd))
+                self.fts.update(msg).map(Message::FTSMessage)
             }
-        };
+            //A broken loader config stops Home from showing anything, so it gets the error screen too
+            Message::HomeMessage(HomeMessage::CatalogLoaded(Err(err))) => self.show_error(err),
+            Message::HomeMessage(msg) => {
+                self.home.update(msg).map(Message::HomeMessage)
+            }
+            Message::ErrorMessage(ErrorMessage::RetryClicked) => {
+                self.state = M4State::LoadingScreen;
+                Self::load_settings()
+            }
+            Message::ErrorMessage(ErrorMessage::StartFreshClicked) => {
+                self.error_screen.back_up_file().map(Message::ErrorMessage)
+            }
+            Message::ErrorMessage(ErrorMessage::FileBackedUp(Ok(_))) => {
+                //Without settings there's nothing to go on but setup,
+                //any other file is just read again without it
+                let is_settings = match (self.error_screen.error(), util::get_config_path()) {
+                    (Some(err), Some(settings)) => err.path() == settings,
+                    _ => false,
+                };
+                if is_settings {
+                    self.settings = M4Settings::default();
+                    return self.go_setup();
+                }
+                self.state = M4State::LoadingScreen;
+                Self::load_settings()
+            }
+            Message::ErrorMessage(msg) => {
+                self.error_screen.update(msg).map(Message::ErrorMessage)
+            }
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        match self.state {
+            M4State::Home => self.home.subscription().map(Message::HomeMessage),
+            _ => Subscription::none(),
+        }
     }
 
     fn view(&mut self) -> Element<'_, Self::Message> {
@@ -103,14 +179,14 @@ impl Application for M4 {
             M4State::LoadingScreen =>
                 Text::new("Loading...").size(40).into(),
 
-            M4State::ErrorScreen(msg) =>
-                Text::new(msg).size(30).into(),
+            M4State::ErrorScreen =>
+                self.error_screen.view().map(Message::ErrorMessage),
 
             M4State::Home =>
-                Text::new("Home").size(30).into(),
+                self.home.view().map(Message::HomeMessage),
 
             M4State::FirstTimeSetup =>
-                self.fts.view().map(Message::FTSMessage).into(),
+                self.fts.view().map(Message::FTSMessage),
         };
 
         Container::new(content)
@@ -1,26 +1,102 @@
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use serde_derive::{Deserialize, Serialize};
+use toml::value::Table;
+use crate::{constants, util};
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+// Bump this and add a step to MIGRATIONS whenever settings.toml changes shape.
+pub const SETTINGS_VERSION: u32 = 1;
+
+// MIGRATIONS[n] upgrades a version n file to version n + 1.
+// Files from before versioning have no schema_version and count as version 0.
+const MIGRATIONS: &[fn(&mut Table)] = &[
+    //0 -> 1: only adds schema_version
+    |_| {},
+];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct M4Settings {
+    #[serde(default)]
+    pub schema_version: u32,
     pub game_dir: String,
+    // Where to look for DivaModLoader releases, see loader_update. None is the official GitHub releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader_feed: Option<String>,
 }
 
+impl Default for M4Settings {
+    fn default() -> Self {
+        Self {
+            schema_version: SETTINGS_VERSION,
+            game_dir: String::new(),
+            loader_feed: None,
+        }
+    }
+}
 
 impl M4Settings {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn save_settings(path: PathBuf, settings: &M4Settings) {
-        let mut file = File::create(path).expect("Failed to create settings file");
-        let data = toml::to_string(&settings).expect("Failed to serialize settings");
-        file.write_all(data.as_bytes()).expect("Failed to write settings to file");
-        file.flush().expect("Failed to flush settings to file");
+    pub fn loader_feed(&self) -> &str {
+        self.loader_feed.as_deref().unwrap_or(constants::LOADER_RELEASE_FEED)
+    }
+
+    // Loads settings.toml, upgrading it first if it was written by an older M4.
+    // The old file is kept as settings.toml.v<version>.bak before anything is changed.
+    pub async fn load(path: PathBuf) -> Result<M4Settings, LoadError> {
+        let source = fs::read_to_string(&path).map_err(|e| LoadError::read(e, &path))?;
+        let mut table: Table = toml::from_str(&source).map_err(|e| LoadError::parse(e, &path))?;
+
+        let version = Self::version_of(&table).map_err(|kind| LoadError(kind, path.clone()))?;
+        if version < SETTINGS_VERSION {
+            let backup = path.with_file_name(format!("{}.v{}.bak",
+                                                     path.file_name().unwrap_or_default().to_string_lossy(), version));
+            fs::copy(&path, &backup).map_err(|e| LoadError::write(e, &backup))?;
+
+            Self::migrate(&mut table, version);
+            let data = toml::to_string(&table)
+                .map_err(|e| LoadError(LoadErrorType::WriteError(e.to_string()), path.clone()))?;
+            util::write_atomic(&path, data.as_bytes()).map_err(|e| LoadError::write(e, &path))?;
+        }
+
+        toml::Value::Table(table).try_into().map_err(|e| LoadError::parse(e, &path))
+    }
+
+    fn version_of(table: &Table) -> Result<u32, LoadErrorType> {
+        let version = match table.get("schema_version") {
+            None => 0,
+            Some(toml::Value::Integer(v)) if *v >= 0 => *v,
+            Some(other) => return Err(LoadErrorType::ParseError {
+                message: format!("schema_version should be a positive number, not {}", other),
+                line: None,
+                column: None,
+            }),
+        };
+        //A newer M4 wrote this, there's no telling what it holds now.
+        //Compared before narrowing, so one too big for a u32 doesn't wrap around to a supported one
+        if version > SETTINGS_VERSION as i64 {
+            return Err(LoadErrorType::UnsupportedVersion(version));
+        }
+        Ok(version as u32)
+    }
+
+    // Runs every migration from `from` up to the current version.
+    pub fn migrate(table: &mut Table, from: u32) {
+        for migration in MIGRATIONS.iter().skip(from as usize) {
+            migration(table);
+        }
+        table.insert(String::from("schema_version"), toml::Value::Integer(SETTINGS_VERSION as i64));
+    }
+
+    pub fn save_settings(path: PathBuf, settings: &M4Settings) -> Result<(), LoadError> {
+        let data = toml::to_string(&settings)
+            .map_err(|e| LoadError(LoadErrorType::WriteError(e.to_string()), path.clone()))?;
+        util::write_atomic(&path, data.as_bytes()).map_err(|e| LoadError::write(e, &path))
     }
 }
 
@@ -28,6 +104,14 @@ impl M4Settings {
 pub enum LoadErrorType {
     FileNotFound,
     CantOpenFile,
+    ReadError(String),
+    // The line and column start at 1, not every TOML error has them
+    ParseError { message: String, line: Option<usize>, column: Option<usize> },
+    WriteError(String),
+    // The file is from a newer version of M4, holds the schema_version as written in it
+    UnsupportedVersion(i64),
+    // There's nowhere to keep settings.toml, see util::get_config_path
+    NoConfigDir,
 }
 
 impl Display for LoadErrorType {
@@ -35,15 +119,73 @@ impl Display for LoadErrorType {
         match self {
             LoadErrorType::FileNotFound => write!(f, "File not found"),
             LoadErrorType::CantOpenFile => write!(f, "Cannot open file"),
+            LoadErrorType::ReadError(err) => write!(f, "Cannot read file: {}", err),
+            LoadErrorType::ParseError { message, line: Some(line), column: Some(column) } => {
+                write!(f, "Invalid TOML at line {}, column {}: {}", line, column, message)
+            }
+            LoadErrorType::ParseError { message, .. } => write!(f, "Invalid TOML: {}", message),
+            LoadErrorType::WriteError(err) => write!(f, "Cannot write file: {}", err),
+            LoadErrorType::UnsupportedVersion(version) => {
+                write!(f, "Made by a newer version of {} (schema version {}, this version knows up to {})",
+                       constants::APPNAME, version, SETTINGS_VERSION)
+            }
+            LoadErrorType::NoConfigDir => write!(f, "Cannot find or create the config directory"),
         }
     }
 }
 
+// What went wrong, and the file it went wrong with.
 #[derive(Debug, Clone)]
-pub struct LoadError(pub(crate) LoadErrorType);
+pub struct LoadError(pub(crate) LoadErrorType, pub(crate) PathBuf);
+
+impl LoadError {
+    pub fn kind(&self) -> &LoadErrorType {
+        &self.0
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.1
+    }
+
+    // A failed read, a missing file gets its own error so callers can treat it as "not set up yet".
+    pub(crate) fn read(err: io::Error, path: &Path) -> Self {
+        let kind = match err.kind() {
+            io::ErrorKind::NotFound => LoadErrorType::FileNotFound,
+            io::ErrorKind::PermissionDenied => LoadErrorType::CantOpenFile,
+            _ => LoadErrorType::ReadError(err.to_string()),
+        };
+        LoadError(kind, path.to_path_buf())
+    }
+
+    pub(crate) fn parse(err: toml::de::Error, path: &Path) -> Self {
+        //toml counts from 0, editors count from 1
+        let (line, column) = match err.line_col() {
+            Some((line, column)) => (Some(line + 1), Some(column + 1)),
+            None => (None, None),
+        };
+        //The message from toml ends with its own " at line x column y", that's shown separately
+        let message = err.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(i) if line.is_some() => message[..i].to_string(),
+            _ => message,
+        };
+        LoadError(LoadErrorType::ParseError { message, line, column }, path.to_path_buf())
+    }
+
+    pub(crate) fn no_config_dir() -> Self {
+        LoadError(LoadErrorType::NoConfigDir, PathBuf::from(constants::SETTINGS_FILE))
+    }
+
+    pub(crate) fn write(err: io::Error, path: &Path) -> Self {
+        LoadError(LoadErrorType::WriteError(err.to_string()), path.to_path_buf())
+    }
+}
 
 impl fmt::Display for LoadError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Failed to load settings: {}", self.0)
+        match self.0 {
+            LoadErrorType::WriteError(_) => write!(f, "Failed to save {}: {}", self.1.display(), self.0),
+            _ => write!(f, "Failed to load {}: {}", self.1.display(), self.0),
+        }
     }
 }
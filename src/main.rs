@@ -1,8 +1,22 @@
+use clap::Parser;
 use iced::{Application, Settings};
+use m4::cli::Cli;
 use m4::m4::M4;
 
 fn main() -> iced::Result {
+    match Cli::try_parse() {
+        Ok(cli) if cli.command.is_some() => std::process::exit(m4::cli::run(cli)),
+        Ok(_) => {}
+        Err(err) => {
+            //--help and --version end up here too
+            if !err.use_stderr() || m4::cli::names_subcommand(std::env::args_os().skip(1)) {
+                err.exit();
+            }
+            //Anything else is from a launcher, the window opens as usual
+        }
+    }
+
     let mut settings: Settings<_> = Settings::default();
     settings.window.min_size = Some((460, 320));
-    M4::run(settings.into())
+    M4::run(settings)
 }
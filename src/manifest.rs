@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_derive::{Deserialize, Serialize};
+use crate::loader_conf::LoaderConfig;
+use crate::m4_settings::{LoadError, LoadErrorType};
+use crate::mod_catalog::{ModCatalog, ModEntry};
+use crate::profile::Profile;
+use crate::util;
+
+//Manifests, a setup shared with other people
+//Like a profile, but it also says which mod and version each entry is,
+//so whoever imports it can see what they're missing.
+//Written as TOML, or JSON when the file ends in .json.
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub manifest_version: u32,
+    // In load order, highest priority first
+    #[serde(default)]
+    pub mods: Vec<ManifestMod>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ManifestMod {
+    pub folder: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub enabled: bool,
+    // 1 based position in the load order
+    pub priority: usize,
+}
+
+impl ManifestMod {
+    pub fn display_name(&self) -> &str {
+        match &self.name {
+            Some(name) if !name.trim().is_empty() => name,
+            _ => &self.folder,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Toml,
+    Json,
+}
+
+impl ManifestFormat {
+    pub fn from_path(path: &Path) -> ManifestFormat {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ManifestFormat::Json,
+            _ => ManifestFormat::Toml,
+        }
+    }
+}
+
+// How a manifest entry compares to what is installed here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryStatus {
+    Installed,
+    DifferentVersion(Option<String>),
+    Missing,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffEntry {
+    pub entry: ManifestMod,
+    // The folder of the matching mod here, it can be named differently
+    pub local_folder: Option<String>,
+    pub status: EntryStatus,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ManifestDiff {
+    pub entries: Vec<DiffEntry>,
+}
+
+impl ManifestDiff {
+    pub fn missing(&self) -> impl Iterator<Item = &DiffEntry> {
+        self.entries.iter().filter(|e| e.status == EntryStatus::Missing)
+    }
+
+    pub fn different_version(&self) -> impl Iterator<Item = &DiffEntry> {
+        self.entries.iter().filter(|e| matches!(e.status, EntryStatus::DifferentVersion(_)))
+    }
+
+    // The part of the manifest that can be applied here, as a profile.
+    pub fn to_profile(&self, name: &str) -> Profile {
+        let mut present: Vec<(&DiffEntry, &String)> = self.entries.iter()
+            .filter_map(|e| e.local_folder.as_ref().map(|folder| (e, folder)))
+            .collect();
+        present.sort_by_key(|(e, _)| e.entry.priority);
+
+        Profile {
+            name: name.to_string(),
+            priority: present.iter().map(|(_, folder)| (*folder).clone()).collect(),
+            mods: present.iter()
+                .map(|(e, folder)| ((*folder).clone(), e.entry.enabled))
+                .collect::<BTreeMap<String, bool>>(),
+            file: None,
+        }
+    }
+}
+
+impl Display for ManifestDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let installed = self.entries.iter().filter(|e| e.local_folder.is_some()).count();
+        write!(f, "{} of {} mods installed", installed, self.entries.len())?;
+        for e in self.missing() {
+            write!(f, "\nMissing: {}", e.entry.display_name())?;
+            if let Some(version) = &e.entry.version {
+                write!(f, " v{}", version)?;
+            }
+        }
+        for e in self.different_version() {
+            if let EntryStatus::DifferentVersion(local) = &e.status {
+                write!(f, "\nDifferent version: {} (v{} here, v{} in the manifest)",
+                       e.entry.display_name(),
+                       local.as_deref().unwrap_or("?"),
+                       e.entry.version.as_deref().unwrap_or("?"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Manifest {
+    // The installed mods, in load order.
+    pub fn export(loader: &LoaderConfig, catalog: &ModCatalog) -> Manifest {
+        let installed: Vec<String> = catalog.mods.iter().map(|m| m.folder_name()).collect();
+        let mods = loader.ordered_mods(&installed).iter()
+            .filter_map(|folder| catalog.find(folder))
+            .enumerate()
+            .map(|(i, m)| ManifestMod {
+                folder: m.folder_name(),
+                name: m.config.name.clone(),
+                version: m.config.version.clone(),
+                author: m.config.author.clone(),
+                enabled: m.config.enabled,
+                priority: i + 1,
+            })
+            .collect();
+        Manifest { manifest_version: MANIFEST_VERSION, mods }
+    }
+
+    // Like `export`, but with the loader config and the mods' configs read again from disk,
+    // so toggles and reorders made since `catalog` was scanned are in it. See Profile::capture.
+    pub fn export_current(game_dir: &Path, catalog: &ModCatalog) -> Result<Manifest, String> {
+        let loader = LoaderConfig::load(game_dir).map_err(|e| e.to_string())?;
+        Ok(Self::export(&loader, &catalog.with_current_configs()))
+    }
+
+    pub fn to_string(&self, format: ManifestFormat) -> Result<String, String> {
+        match format {
+            ManifestFormat::Toml => toml::to_string(self).map_err(|e| e.to_string()),
+            ManifestFormat::Json => serde_json::to_string_pretty(self).map(|s| s + "\n").map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn from_str(source: &str, format: ManifestFormat) -> Result<Manifest, String> {
+        let manifest: Manifest = match format {
+            ManifestFormat::Toml => toml::from_str(source).map_err(|e| e.to_string())?,
+            ManifestFormat::Json => serde_json::from_str(source).map_err(|e| e.to_string())?,
+        };
+        if manifest.manifest_version > MANIFEST_VERSION {
+            return Err(format!("Made by a newer version of M4 (manifest version {})", manifest.manifest_version));
+        }
+        Ok(manifest)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let data = self.to_string(ManifestFormat::from_path(path))?;
+        util::write_atomic_no_backup(path, data.as_bytes()).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Manifest, LoadError> {
+        let source = fs::read_to_string(path).map_err(|e| LoadError::read(e, path))?;
+        Self::from_str(&source, ManifestFormat::from_path(path)).map_err(|message| {
+            LoadError(LoadErrorType::ParseError { message, line: None, column: None }, path.to_path_buf())
+        })
+    }
+
+    // Matches every entry to an installed mod, by folder first and by name if the folder isn't there.
+    pub fn diff(&self, catalog: &ModCatalog) -> ManifestDiff {
+        let entries = self.mods.iter().map(|entry| {
+            let local = catalog.find(&entry.folder).or_else(|| find_by_name(catalog, entry));
+            let status = match local {
+                None => EntryStatus::Missing,
+                Some(m) if entry.version.is_some() && m.config.version != entry.version => {
+                    EntryStatus::DifferentVersion(m.config.version.clone())
+                }
+                Some(_) => EntryStatus::Installed,
+            };
+            DiffEntry {
+                entry: entry.clone(),
+                local_folder: local.map(|m| m.folder_name()),
+                status,
+            }
+        }).collect();
+        ManifestDiff { entries }
+    }
+}
+
+fn find_by_name<'a>(catalog: &'a ModCatalog, entry: &ManifestMod) -> Option<&'a ModEntry> {
+    let name = entry.name.as_ref()?;
+    let mut matches = catalog.mods.iter().filter(|m| m.display_name().eq_ignore_ascii_case(name));
+    match (matches.next(), matches.next()) {
+        (Some(m), None) => Some(m),
+        //Ambiguous, better to call it missing than to pick the wrong one
+        _ => None,
+    }
+}
+
+pub async fn export_to(path: PathBuf, game_dir: PathBuf, catalog: ModCatalog) -> Result<PathBuf, String> {
+    util::run_blocking(move || {
+        Manifest::export_current(&game_dir, &catalog)?.save(&path)?;
+        Ok(path)
+    }).await
+}
+
+pub async fn load_from(path: PathBuf) -> Result<Manifest, LoadError> {
+    util::run_blocking(move || Manifest::load(&path)).await
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::constants;
+use crate::farc;
+use crate::farc::ModFarc;
+use crate::file_index;
+use crate::file_index::ModFile;
+use crate::m4_settings::LoadError;
+use crate::mod_conf::ModConfig;
+use crate::pv_db;
+use crate::pv_db::PvEntry;
+use crate::util;
+
+//The mod catalog is everything M4 knows about the mods folder.
+//Every folder inside of it is a mod, and every mod has a config.toml,
+//mods that we can't read end up in `errors` so one broken mod doesn't take the rest with it.
+
+#[derive(Debug, Clone)]
+pub struct ModEntry {
+    // The folder of the mod, e.g <game_dir>/mods/SomeMod
+    pub path: PathBuf,
+    pub config: ModConfig,
+    // The game files the mod replaces, see file_index
+    pub files: Vec<ModFile>,
+    // Songs from the mod's mod_pv_db.txt, see pv_db
+    pub pvs: Vec<PvEntry>,
+    // Headers of the mod's .farc files, see farc
+    pub archives: Vec<ModFarc>,
+}
+
+impl ModEntry {
+    pub fn folder_name(&self) -> String {
+        folder_name(&self.path)
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.path.join(constants::MOD_CONFIG_FILE)
+    }
+
+    // The name from the config, or the folder name if the author didn't give one.
+    pub fn display_name(&self) -> String {
+        match &self.config.name {
+            Some(name) if !name.trim().is_empty() => name.clone(),
+            _ => self.folder_name(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ModLoadErrorType {
+    MissingConfig,
+    CantReadConfig(String),
+    BadToml(String),
+}
+
+impl Display for ModLoadErrorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ModLoadErrorType::MissingConfig => write!(f, "No {} found", constants::MOD_CONFIG_FILE),
+            ModLoadErrorType::CantReadConfig(err) => write!(f, "Cannot read {}: {}", constants::MOD_CONFIG_FILE, err),
+            ModLoadErrorType::BadToml(err) => write!(f, "Invalid {}: {}", constants::MOD_CONFIG_FILE, err),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModLoadError {
+    // The folder of the mod that failed to load
+    pub path: PathBuf,
+    pub error: ModLoadErrorType,
+}
+
+impl ModLoadError {
+    pub fn folder_name(&self) -> String {
+        folder_name(&self.path)
+    }
+}
+
+impl Display for ModLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.folder_name(), self.error)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModCatalog {
+    pub mods_dir: PathBuf,
+    pub mods: Vec<ModEntry>,
+    pub errors: Vec<ModLoadError>,
+}
+
+impl ModCatalog {
+    // Where DivaModLoader looks for mods by default.
+    pub fn default_mods_dir(game_dir: &Path) -> PathBuf {
+        game_dir.join(constants::MODS_DIR)
+    }
+
+    // Walks every folder in `mods_dir`, loads its config.toml and indexes its files, songs and archives.
+    // A missing mods folder is just an empty catalog, the loader creates it on first launch.
+    pub fn scan(mods_dir: &Path) -> Result<ModCatalog, LoadError> {
+        let mut catalog = ModCatalog {
+            mods_dir: mods_dir.to_path_buf(),
+            ..Default::default()
+        };
+
+        if !mods_dir.exists() {
+            return Ok(catalog);
+        }
+
+        let dir = fs::read_dir(mods_dir).map_err(|e| LoadError::read(e, mods_dir))?;
+        let mut folders: Vec<PathBuf> = dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            //Hidden folders (.git and the like) are skipped, they aren't mods
+            .filter(|path| !folder_name(path).starts_with('.'))
+            .collect();
+        folders.sort();
+
+        for folder in folders {
+            match Self::load_mod(&folder) {
+                Ok(config) => {
+                    let files = file_index::index_mod(&folder, &config);
+                    catalog.mods.push(ModEntry {
+                        pvs: pv_db::index_mod(&files),
+                        archives: farc::index_mod(&files),
+                        files,
+                        path: folder,
+                        config,
+                    })
+                }
+                Err(error) => catalog.errors.push(ModLoadError { path: folder, error }),
+            }
+        }
+
+        Ok(catalog)
+    }
+
+    pub async fn load(mods_dir: PathBuf) -> Result<ModCatalog, LoadError> {
+        util::run_blocking(move || Self::scan(&mods_dir)).await
+    }
+
+    fn load_mod(folder: &Path) -> Result<ModConfig, ModLoadErrorType> {
+        let config_path = folder.join(constants::MOD_CONFIG_FILE);
+        if !config_path.is_file() {
+            return Err(ModLoadErrorType::MissingConfig);
+        }
+
+        let buffer = fs::read_to_string(&config_path)
+            .map_err(|e| ModLoadErrorType::CantReadConfig(e.to_string()))?;
+
+        toml::from_str(&buffer).map_err(|e| ModLoadErrorType::BadToml(e.to_string()))
+    }
+
+    // A copy with every mod's config.toml read again, for when mods may have been toggled since the scan.
+    // A mod whose config can't be read anymore keeps the one from the scan.
+    pub fn with_current_configs(&self) -> ModCatalog {
+        let mut catalog = self.clone();
+        for entry in &mut catalog.mods {
+            if let Ok(config) = Self::load_mod(&entry.path) {
+                entry.config = config;
+            }
+        }
+        catalog
+    }
+
+    pub fn find(&self, folder_name: &str) -> Option<&ModEntry> {
+        self.mods.iter().find(|m| m.folder_name() == folder_name)
+    }
+
+    pub fn find_mut(&mut self, folder_name: &str) -> Option<&mut ModEntry> {
+        self.mods.iter_mut().find(|m| m.folder_name() == folder_name)
+    }
+}
+
+fn folder_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
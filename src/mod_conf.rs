@@ -1,8 +1,10 @@
 use std::fmt;
 use std::fmt::Display;
+use std::path::{Path, PathBuf};
 use serde_derive::{Deserialize, Serialize};
+use crate::{toml_patch, util};
 
-#[derive(Deserialize, Serialize, Default)]
+#[derive(Deserialize, Serialize, Default, Clone)]
 pub struct ModConfig {
     pub enabled: bool,
     pub include: Option<Vec<String>>,
@@ -20,7 +22,6 @@ fn check_field<T: Display>(f: &mut std::fmt::Formatter<'_>, name: &str, infield:
         return;
     }
     write!(f, "{}: \"{}\", ", name, infield.as_ref().unwrap()).unwrap();
-    return;
 }
 
 impl fmt::Debug for ModConfig {
@@ -32,16 +33,16 @@ impl fmt::Debug for ModConfig {
         check_field(f, "date", &self.date);
         check_field(f, "author", &self.author);
 
-        if self.include.is_some() {
+        if let Some(include) = &self.include {
             write!(f, "include: [")?;
-            for i in self.include.as_ref().unwrap() {
+            for i in include {
                 write!(f, "{}, ", i)?;
             }
             write!(f, "], ")?;
         }
-        if self.dll.is_some() {
+        if let Some(dll) = &self.dll {
             write!(f, "dll: [")?;
-            for i in self.dll.as_ref().unwrap() {
+            for i in dll {
                 write!(f, "{}, ", i)?;
             }
             write!(f, "], ")?;
@@ -49,4 +50,19 @@ impl fmt::Debug for ModConfig {
 
         write!(f, "}}")
     }
-}
\ No newline at end of file
+}
+
+impl ModConfig {
+    // Keys that describe the mod, as opposed to `enabled` and settings the user can change.
+    pub const METADATA_KEYS: &'static [&'static str] = &["include", "dll", "name", "description", "version", "date", "author"];
+
+    // Flips `enabled` in the mod's config.toml, returns the new state so the UI can confirm it.
+    // Only the `enabled` line changes, the rest of the file is left as the author wrote it.
+    pub async fn save_enabled(path: PathBuf, enabled: bool) -> Result<bool, String> {
+        util::run_blocking(move || Self::write_enabled(&path, enabled).map(|_| enabled)).await
+    }
+
+    pub fn write_enabled(path: &Path, enabled: bool) -> Result<(), String> {
+        toml_patch::patch_file(path, |doc| toml_patch::set_value(doc, "enabled", enabled))
+    }
+}
@@ -0,0 +1,256 @@
+use std::path::{Path, PathBuf};
+use iced::{Alignment, Button, button, Color, Column, Command, Element, keyboard, Length, Row, Scrollable, scrollable, Subscription, Text};
+use iced_native::{Event, subscription};
+use iced_native::event::Status;
+use crate::constants;
+use crate::loader_conf::LoaderConfig;
+use crate::mod_catalog::ModCatalog;
+use crate::style::M4ButtonStyleSheet;
+
+//Priority, the load order view on the home screen
+//The mod at the top wins when two mods replace the same file.
+//Keyboard: Up/Down selects a mod, Alt+Up/Alt+Down moves the selected mod.
+
+#[derive(Debug, Clone)]
+pub enum PriorityMessage {
+    Select(usize),
+    MoveUp(usize),
+    MoveDown(usize),
+    Key(PriorityKey),
+    Saved(Result<Vec<String>, String>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PriorityKey {
+    SelectPrev,
+    SelectNext,
+    MoveUp,
+    MoveDown,
+}
+
+struct PriorityRow {
+    folder: String,
+    name: String,
+    select_btn: button::State,
+    up_btn: button::State,
+    down_btn: button::State,
+}
+
+pub struct PriorityList {
+    game_dir: PathBuf,
+    loader: LoaderConfig,
+    rows: Vec<PriorityRow>,
+    selected: Option<usize>,
+    // Only one save runs at a time, moves done while saving get saved after it.
+    saving: bool,
+    save_pending: bool,
+    error: Option<String>,
+    scroll: scrollable::State,
+}
+
+impl Default for PriorityList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriorityList {
+    pub fn new() -> Self {
+        Self {
+            game_dir: PathBuf::new(),
+            loader: LoaderConfig::default(),
+            rows: Vec::new(),
+            selected: None,
+            saving: false,
+            save_pending: false,
+            error: None,
+            scroll: scrollable::State::new(),
+        }
+    }
+
+    pub fn set_mods(&mut self, game_dir: &Path, loader: &LoaderConfig, catalog: &ModCatalog) {
+        self.game_dir = game_dir.to_path_buf();
+        self.loader = loader.clone();
+
+        let installed: Vec<String> = catalog.mods.iter().map(|m| m.folder_name()).collect();
+        self.rows = loader.ordered_mods(&installed)
+            .into_iter()
+            .map(|folder| PriorityRow {
+                name: catalog.find(&folder).map(|m| m.display_name()).unwrap_or_else(|| folder.clone()),
+                folder,
+                select_btn: button::State::new(),
+                up_btn: button::State::new(),
+                down_btn: button::State::new(),
+            })
+            .collect();
+        self.selected = None;
+        self.error = None;
+    }
+
+    pub fn order(&self) -> Vec<String> {
+        self.rows.iter().map(|r| r.folder.clone()).collect()
+    }
+
+    pub fn update(&mut self, message: PriorityMessage) -> Command<PriorityMessage> {
+        match message {
+            PriorityMessage::Select(index) => {
+                self.selected = Some(index);
+                Command::none()
+            }
+            PriorityMessage::MoveUp(index) => self.move_row(index, true),
+            PriorityMessage::MoveDown(index) => self.move_row(index, false),
+            PriorityMessage::Key(key) => {
+                if self.rows.is_empty() {
+                    return Command::none();
+                }
+                let last = self.rows.len() - 1;
+                match (key, self.selected) {
+                    (PriorityKey::SelectPrev, Some(i)) => self.selected = Some(i.saturating_sub(1)),
+                    (PriorityKey::SelectNext, Some(i)) => self.selected = Some((i + 1).min(last)),
+                    (PriorityKey::SelectPrev, None) => self.selected = Some(last),
+                    (PriorityKey::SelectNext, None) => self.selected = Some(0),
+                    (PriorityKey::MoveUp, Some(i)) => return self.move_row(i, true),
+                    (PriorityKey::MoveDown, Some(i)) => return self.move_row(i, false),
+                    _ => {}
+                }
+                Command::none()
+            }
+            PriorityMessage::Saved(res) => {
+                self.saving = false;
+                match res {
+                    Ok(priority) => {
+                        self.loader.priority = priority;
+                        self.error = None;
+                    }
+                    Err(err) => {
+                        self.error = Some(format!("Failed to save the load order: {}", err));
+                        //A later move still gets its own try, otherwise show what's actually saved
+                        if !self.save_pending {
+                            self.restore_saved_order();
+                        }
+                    }
+                }
+                if self.save_pending {
+                    return self.save();
+                }
+                Command::none()
+            }
+        }
+    }
+
+    // Puts the rows back in the order of the last priority list that was saved.
+    fn restore_saved_order(&mut self) {
+        let selected = self.selected.and_then(|i| self.rows.get(i)).map(|r| r.folder.clone());
+        let order = self.loader.ordered_mods(&self.order());
+        self.rows.sort_by_key(|r| order.iter().position(|f| *f == r.folder));
+        self.selected = selected.and_then(|folder| self.rows.iter().position(|r| r.folder == folder));
+    }
+
+    fn move_row(&mut self, index: usize, up: bool) -> Command<PriorityMessage> {
+        let target = if up { index.checked_sub(1) } else { Some(index + 1) };
+        let target = match target {
+            Some(target) if target < self.rows.len() => target,
+            _ => return Command::none(),
+        };
+
+        self.rows.swap(index, target);
+        self.selected = Some(target);
+        self.save()
+    }
+
+    fn save(&mut self) -> Command<PriorityMessage> {
+        if self.saving {
+            self.save_pending = true;
+            return Command::none();
+        }
+
+        self.saving = true;
+        self.save_pending = false;
+        let priority = self.loader.priority_for(&self.order());
+        Command::perform(
+            LoaderConfig::save_priority(self.game_dir.clone(), priority),
+            PriorityMessage::Saved)
+    }
+
+    pub fn subscription(&self) -> Subscription<PriorityMessage> {
+        subscription::events_with(|event, status| {
+            if status == Status::Captured {
+                return None;
+            }
+            match event {
+                Event::Keyboard(keyboard::Event::KeyPressed { key_code, modifiers }) => {
+                    let key = match (key_code, modifiers.alt()) {
+                        (keyboard::KeyCode::Up, false) => PriorityKey::SelectPrev,
+                        (keyboard::KeyCode::Down, false) => PriorityKey::SelectNext,
+                        (keyboard::KeyCode::Up, true) => PriorityKey::MoveUp,
+                        (keyboard::KeyCode::Down, true) => PriorityKey::MoveDown,
+                        _ => return None,
+                    };
+                    Some(PriorityMessage::Key(key))
+                }
+                _ => None,
+            }
+        })
+    }
+
+    pub fn view(&mut self) -> Element<'_, PriorityMessage> {
+        let PriorityList {
+            rows,
+            selected,
+            error,
+            scroll,
+            ..
+        } = self;
+
+        let mut content = Column::new()
+            .spacing(10)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .push(
+                Text::new("Mods at the top win. Up/Down to select, Alt+Up/Alt+Down to move.")
+                    .size(16)
+                    .color(Color::from_rgb(0.4, 0.4, 0.4))
+            );
+
+        if let Some(error) = error {
+            content = content.push(Text::new(error.as_str()).color(Color::from_rgb(0.8, 0., 0.)));
+        }
+
+        let count = rows.len();
+        let mut list = Scrollable::new(scroll)
+            .spacing(5)
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        for (i, row) in rows.iter_mut().enumerate() {
+            let is_selected = *selected == Some(i);
+            let name_color = if is_selected { *constants::M4BLUE } else { Color::BLACK };
+
+            let mut up = Button::new(&mut row.up_btn, Text::new("Up")).style(M4ButtonStyleSheet);
+            if i > 0 {
+                up = up.on_press(PriorityMessage::MoveUp(i));
+            }
+            let mut down = Button::new(&mut row.down_btn, Text::new("Down")).style(M4ButtonStyleSheet);
+            if i + 1 < count {
+                down = down.on_press(PriorityMessage::MoveDown(i));
+            }
+
+            list = list.push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(format!("{}.", i + 1)).width(Length::Units(40)))
+                    .push(
+                        Button::new(&mut row.select_btn, Text::new(row.name.as_str()).color(name_color))
+                            .width(Length::Fill)
+                            .style(M4ButtonStyleSheet)
+                            .on_press(PriorityMessage::Select(i))
+                    )
+                    .push(up)
+                    .push(down)
+            );
+        }
+
+        content.push(list).into()
+    }
+}
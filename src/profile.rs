@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_derive::{Deserialize, Serialize};
+use crate::{constants, installer, util};
+use crate::loader_conf::LoaderConfig;
+use crate::m4_settings::LoadError;
+use crate::mod_catalog::ModCatalog;
+use crate::mod_conf::ModConfig;
+
+//Profiles, saved mod setups that can be switched between
+//A profile remembers which mods are on and the load order,
+//they live in M4's config directory as <name>.toml so they work for any game directory.
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Profile {
+    pub name: String,
+    // The load order when the profile was saved, highest priority first
+    #[serde(default)]
+    pub priority: Vec<String>,
+    // Folder name -> enabled
+    #[serde(default)]
+    pub mods: BTreeMap<String, bool>,
+    // The file the profile was read from, None if it hasn't been saved yet
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+}
+
+// What applying a profile did.
+#[derive(Debug, Clone, Default)]
+pub struct ApplyReport {
+    // Mods that got turned on or off
+    pub changed: Vec<String>,
+    // Mods in the profile that aren't installed anymore
+    pub missing: Vec<String>,
+}
+
+impl Profile {
+    // Where profiles are kept, next to settings.toml.
+    pub fn dir() -> Option<PathBuf> {
+        Some(util::get_config_path()?.parent()?.join(constants::PROFILES_DIR))
+    }
+
+    // The current setup of the game, as a profile.
+    // `catalog` is only used for which mods are installed, the loader config and the mods' configs
+    // are read again since mods may have been toggled or moved after it was scanned.
+    pub fn capture(name: &str, game_dir: &Path, catalog: &ModCatalog) -> Result<Profile, String> {
+        let loader = LoaderConfig::load(game_dir).map_err(|e| e.to_string())?;
+        let catalog = catalog.with_current_configs();
+        let installed: Vec<String> = catalog.mods.iter().map(|m| m.folder_name()).collect();
+        Ok(Profile {
+            name: name.trim().to_string(),
+            priority: loader.ordered_mods(&installed),
+            mods: catalog.mods.iter().map(|m| (m.folder_name(), m.config.enabled)).collect(),
+            file: None,
+        })
+    }
+
+    pub fn path_in(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.toml", installer::sanitize_folder_name(&self.name)))
+    }
+
+    pub fn load(path: &Path) -> Result<Profile, LoadError> {
+        let buffer = fs::read_to_string(path).map_err(|e| LoadError::read(e, path))?;
+        let mut profile: Profile = toml::from_str(&buffer).map_err(|e| LoadError::parse(e, path))?;
+        profile.file = Some(path.to_path_buf());
+        Ok(profile)
+    }
+
+    // Every profile in `dir` sorted by name, and the files that couldn't be read.
+    pub fn list(dir: &Path) -> (Vec<Profile>, Vec<LoadError>) {
+        let mut profiles = Vec::new();
+        let mut errors = Vec::new();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            //No profiles saved yet
+            Err(_) => return (profiles, errors),
+        };
+
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension().map(|ext| ext != "toml").unwrap_or(true) {
+                continue;
+            }
+            match Self::load(&path) {
+                Ok(profile) => profiles.push(profile),
+                Err(err) => errors.push(err),
+            }
+        }
+        profiles.sort_by_key(|p| p.name.to_lowercase());
+        (profiles, errors)
+    }
+
+    // Saves the profile into `dir`, replacing one with the same name.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        if self.name.is_empty() {
+            return Err(String::from("The profile needs a name"));
+        }
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let path = self.path_in(dir);
+        //A/B and A_B end up in the same file, don't write over the other one
+        if let Ok(existing) = Self::load(&path) {
+            if existing.name != self.name {
+                return Err(format!("\"{}\" would replace the profile \"{}\", pick another name", self.name, existing.name));
+            }
+        }
+        let data = toml::to_string(self).map_err(|e| e.to_string())?;
+        util::write_atomic(&path, data.as_bytes()).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    // Removes the file the profile was read from.
+    pub fn delete(&self) -> Result<(), String> {
+        let file = self.file.as_ref().ok_or_else(|| String::from("The profile was never saved"))?;
+        fs::remove_file(file).map_err(|e| e.to_string())
+    }
+
+    // Turns mods on and off and sets the load order to match the profile.
+    // Installed mods the profile doesn't know about are left as they are.
+    // If any file can't be written the mods already changed are put back,
+    // so a profile is either applied completely or not at all.
+    // What's on disk is compared against, not what `catalog` had when it was scanned, see `capture`.
+    pub fn apply(&self, game_dir: &Path, catalog: &ModCatalog) -> Result<ApplyReport, String> {
+        let loader = &LoaderConfig::load(game_dir).map_err(|e| e.to_string())?;
+        let catalog = &catalog.with_current_configs();
+        let mut report = ApplyReport::default();
+
+        let res = self.write_mods(catalog, &mut report).and_then(|_| {
+            let mut new_loader = loader.clone();
+            new_loader.priority = loader.priority_for(&self.priority);
+            if new_loader.priority == loader.priority {
+                return Ok(());
+            }
+            new_loader.save(game_dir)
+                .map_err(|e| format!("Failed to save the mod loader config: {}", e))
+        });
+
+        if let Err(err) = res {
+            for folder in &report.changed {
+                if let Some(entry) = catalog.find(folder) {
+                    let _ = ModConfig::write_enabled(&entry.config_path(), entry.config.enabled);
+                }
+            }
+            return Err(err);
+        }
+        Ok(report)
+    }
+
+    fn write_mods(&self, catalog: &ModCatalog, report: &mut ApplyReport) -> Result<(), String> {
+        for (folder, enabled) in &self.mods {
+            let entry = match catalog.find(folder) {
+                Some(entry) => entry,
+                None => {
+                    report.missing.push(folder.clone());
+                    continue;
+                }
+            };
+            if entry.config.enabled != *enabled {
+                ModConfig::write_enabled(&entry.config_path(), *enabled)
+                    .map_err(|e| format!("Failed to save {}: {}", folder, e))?;
+                report.changed.push(folder.clone());
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn apply_to(self, game_dir: PathBuf, catalog: ModCatalog) -> Result<ApplyReport, String> {
+        util::run_blocking(move || self.apply(&game_dir, &catalog)).await
+    }
+}
@@ -0,0 +1,402 @@
+use std::path::{Path, PathBuf};
+use ashpd::desktop::file_chooser::{FileChooserProxy, FileFilter, OpenFileOptions, SaveFileOptions};
+use ashpd::{WindowIdentifier, zbus};
+use iced::{Alignment, Button, button, Color, Column, Command, Element, Length, Row, Scrollable, scrollable, Text, TextInput, text_input};
+use crate::m4_settings::LoadError;
+use crate::manifest;
+use crate::manifest::{Manifest, ManifestDiff};
+use crate::mod_catalog::ModCatalog;
+use crate::profile::{ApplyReport, Profile};
+use crate::style::{M4ButtonStyleSheet, M4InputStyleSheet};
+use crate::util;
+use url::Url;
+
+//Profiles tab on the home screen
+//Saves the current setup under a name, and switches back to it later.
+//Setups are shared with other people through manifests, exported and imported from here too.
+
+#[derive(Debug, Clone)]
+pub enum ProfileMessage {
+    Loaded((Vec<Profile>, Vec<LoadError>)),
+    NameChanged(String),
+    SaveClicked,
+    Saved(Result<PathBuf, String>),
+    ApplyClicked(usize),
+    // Name of the profile, and what applying it did
+    Applied(String, Result<ApplyReport, String>),
+    DeleteClicked(usize),
+    ExportClicked,
+    ExportPathChosen(Option<PathBuf>),
+    Exported(Result<PathBuf, String>),
+    ImportClicked,
+    ImportPathChosen(Option<PathBuf>),
+    ImportLoaded(PathBuf, Result<Manifest, LoadError>),
+    // Apply the manifest waiting in `import`, or drop it
+    ImportConfirmed(bool),
+}
+
+// A manifest that was read and compared with the installed mods,
+// waiting for the user to apply it.
+struct PendingImport {
+    name: String,
+    diff: ManifestDiff,
+    apply_btn: button::State,
+    cancel_btn: button::State,
+}
+
+struct ProfileRow {
+    profile: Profile,
+    apply_btn: button::State,
+    delete_btn: button::State,
+}
+
+pub struct ProfileList {
+    game_dir: PathBuf,
+    catalog: ModCatalog,
+    rows: Vec<ProfileRow>,
+    errors: Vec<LoadError>,
+    name: String,
+    name_input: text_input::State,
+    save_btn: button::State,
+    export_btn: button::State,
+    import_btn: button::State,
+    import: Option<PendingImport>,
+    // Saving or applying, nothing else can be done until it's finished
+    busy: bool,
+    status: Option<String>,
+    scroll: scrollable::State,
+}
+
+impl Default for ProfileList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProfileList {
+    pub fn new() -> Self {
+        Self {
+            game_dir: PathBuf::new(),
+            catalog: ModCatalog::default(),
+            rows: Vec::new(),
+            errors: Vec::new(),
+            name: String::new(),
+            name_input: text_input::State::new(),
+            save_btn: button::State::new(),
+            export_btn: button::State::new(),
+            import_btn: button::State::new(),
+            import: None,
+            busy: false,
+            status: None,
+            scroll: scrollable::State::new(),
+        }
+    }
+
+    // Called with every (re)load of the mods, also rereads the saved profiles.
+    pub fn set_mods(&mut self, game_dir: &Path, catalog: &ModCatalog) -> Command<ProfileMessage> {
+        self.game_dir = game_dir.to_path_buf();
+        self.catalog = catalog.clone();
+        Self::reload()
+    }
+
+    fn reload() -> Command<ProfileMessage> {
+        Command::perform(list_profiles(), ProfileMessage::Loaded)
+    }
+
+    // True when a profile was just applied, the mods need to be read again then.
+    pub fn needs_reload(message: &ProfileMessage) -> bool {
+        matches!(message, ProfileMessage::Applied(_, Ok(_)))
+    }
+
+    pub fn update(&mut self, message: ProfileMessage) -> Command<ProfileMessage> {
+        match message {
+            ProfileMessage::Loaded((profiles, errors)) => {
+                self.rows = profiles.into_iter()
+                    .map(|profile| ProfileRow {
+                        profile,
+                        apply_btn: button::State::new(),
+                        delete_btn: button::State::new(),
+                    })
+                    .collect();
+                self.errors = errors;
+                Command::none()
+            }
+            ProfileMessage::NameChanged(name) => {
+                self.name = name;
+                Command::none()
+            }
+            ProfileMessage::SaveClicked => {
+                let dir = match Profile::dir() {
+                    Some(dir) => dir,
+                    None => return Command::none(),
+                };
+                self.busy = true;
+                Command::perform(
+                    save_profile(self.name.clone(), self.game_dir.clone(), self.catalog.clone(), dir),
+                    ProfileMessage::Saved)
+            }
+            ProfileMessage::Saved(res) => {
+                self.busy = false;
+                match res {
+                    Ok(_) => {
+                        self.status = Some(format!("Saved \"{}\"", self.name.trim()));
+                        self.name.clear();
+                        Self::reload()
+                    }
+                    Err(err) => {
+                        self.status = Some(format!("Failed to save the profile: {}", err));
+                        Command::none()
+                    }
+                }
+            }
+            ProfileMessage::ApplyClicked(index) => {
+                let profile = match self.rows.get(index) {
+                    Some(row) => row.profile.clone(),
+                    None => return Command::none(),
+                };
+                self.busy = true;
+                let name = profile.name.clone();
+                Command::perform(
+                    profile.apply_to(self.game_dir.clone(), self.catalog.clone()),
+                    move |res| ProfileMessage::Applied(name.clone(), res))
+            }
+            ProfileMessage::Applied(name, res) => {
+                self.busy = false;
+                self.status = Some(match res {
+                    Ok(report) => describe_report(&name, &report),
+                    Err(err) => format!("Failed to apply \"{}\", nothing was changed: {}", name, err),
+                });
+                Command::none()
+            }
+            ProfileMessage::DeleteClicked(index) => {
+                if let Some(row) = self.rows.get(index) {
+                    if let Err(err) = row.profile.delete() {
+                        self.status = Some(format!("Failed to delete \"{}\": {}", row.profile.name, err));
+                    }
+                }
+                Self::reload()
+            }
+            ProfileMessage::ExportClicked => {
+                Command::perform(choose_export_path(), ProfileMessage::ExportPathChosen)
+            }
+            ProfileMessage::ExportPathChosen(path) => {
+                let path = match path {
+                    Some(path) => path,
+                    None => return Command::none(),
+                };
+                Command::perform(
+                    manifest::export_to(path, self.game_dir.clone(), self.catalog.clone()),
+                    ProfileMessage::Exported)
+            }
+            ProfileMessage::Exported(res) => {
+                self.status = Some(match res {
+                    Ok(path) => format!("Exported to {}", path.display()),
+                    Err(err) => format!("Failed to export: {}", err),
+                });
+                Command::none()
+            }
+            ProfileMessage::ImportClicked => {
+                Command::perform(choose_manifest(), ProfileMessage::ImportPathChosen)
+            }
+            ProfileMessage::ImportPathChosen(path) => {
+                match path {
+                    Some(path) => Command::perform(
+                        manifest::load_from(path.clone()),
+                        move |res| ProfileMessage::ImportLoaded(path.clone(), res)),
+                    None => Command::none(),
+                }
+            }
+            ProfileMessage::ImportLoaded(path, res) => {
+                match res {
+                    Ok(manifest) => {
+                        self.status = None;
+                        self.import = Some(PendingImport {
+                            name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+                            diff: manifest.diff(&self.catalog),
+                            apply_btn: button::State::new(),
+                            cancel_btn: button::State::new(),
+                        });
+                    }
+                    Err(err) => self.status = Some(err.to_string()),
+                }
+                Command::none()
+            }
+            ProfileMessage::ImportConfirmed(apply) => {
+                let import = match self.import.take() {
+                    Some(import) if apply => import,
+                    _ => return Command::none(),
+                };
+                self.busy = true;
+                let profile = import.diff.to_profile(&import.name);
+                let name = import.name;
+                Command::perform(
+                    profile.apply_to(self.game_dir.clone(), self.catalog.clone()),
+                    move |res| ProfileMessage::Applied(name.clone(), res))
+            }
+        }
+    }
+
+    pub fn view(&mut self) -> Element<'_, ProfileMessage> {
+        let ProfileList {
+            rows,
+            errors,
+            name,
+            name_input,
+            save_btn,
+            export_btn,
+            import_btn,
+            import,
+            busy,
+            status,
+            scroll,
+            ..
+        } = self;
+
+        let mut save = Button::new(save_btn, Text::new("Save current setup")).style(M4ButtonStyleSheet);
+        if !*busy && !name.trim().is_empty() {
+            save = save.on_press(ProfileMessage::SaveClicked);
+        }
+
+        let mut content = Column::new()
+            .spacing(10)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .push(
+                Text::new("A profile remembers which mods are on and the load order.")
+                    .size(16)
+                    .color(Color::from_rgb(0.4, 0.4, 0.4))
+            )
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(TextInput::new(name_input, "Profile name", name, ProfileMessage::NameChanged)
+                        .style(M4InputStyleSheet { show_validity: false, is_valid: true })
+                        .padding(3)
+                        .width(Length::Fill))
+                    .push(save)
+                    .push(Button::new(export_btn, Text::new("Export"))
+                        .style(M4ButtonStyleSheet)
+                        .on_press(ProfileMessage::ExportClicked))
+                    .push({
+                        let btn = Button::new(import_btn, Text::new("Import")).style(M4ButtonStyleSheet);
+                        if *busy { btn } else { btn.on_press(ProfileMessage::ImportClicked) }
+                    })
+            );
+
+        if let Some(import) = import {
+            content = content.push(Self::import_prompt(import));
+        }
+
+        if let Some(status) = status {
+            content = content.push(Text::new(status.as_str()).size(16));
+        }
+
+        let mut list = Scrollable::new(scroll)
+            .spacing(5)
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        if rows.is_empty() {
+            list = list.push(Text::new("No profiles yet."));
+        }
+
+        for (i, row) in rows.iter_mut().enumerate() {
+            let enabled = row.profile.mods.values().filter(|e| **e).count();
+            let mut apply = Button::new(&mut row.apply_btn, Text::new("Apply")).style(M4ButtonStyleSheet);
+            let mut delete = Button::new(&mut row.delete_btn, Text::new("Delete")).style(M4ButtonStyleSheet);
+            if !*busy {
+                apply = apply.on_press(ProfileMessage::ApplyClicked(i));
+                delete = delete.on_press(ProfileMessage::DeleteClicked(i));
+            }
+
+            list = list.push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(
+                        Column::new()
+                            .width(Length::Fill)
+                            .push(Text::new(row.profile.name.as_str()).size(24))
+                            .push(Text::new(format!("{} of {} mods on", enabled, row.profile.mods.len()))
+                                .size(16)
+                                .color(Color::from_rgb(0.4, 0.4, 0.4)))
+                    )
+                    .push(apply)
+                    .push(delete)
+            );
+        }
+
+        for err in errors.iter() {
+            list = list.push(Text::new(err.to_string()).size(16).color(Color::from_rgb(0.8, 0., 0.)));
+        }
+
+        content.push(list).into()
+    }
+
+    fn import_prompt(import: &mut PendingImport) -> Element<'_, ProfileMessage> {
+        Column::new()
+            .spacing(5)
+            .push(Text::new(format!("Import \"{}\"? Only installed mods are changed.", import.name)))
+            .push(Text::new(import.diff.to_string()).size(16))
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(Button::new(&mut import.apply_btn, Text::new("Apply"))
+                        .style(M4ButtonStyleSheet)
+                        .on_press(ProfileMessage::ImportConfirmed(true)))
+                    .push(Button::new(&mut import.cancel_btn, Text::new("Cancel"))
+                        .style(M4ButtonStyleSheet)
+                        .on_press(ProfileMessage::ImportConfirmed(false)))
+            )
+            .into()
+    }
+}
+
+fn describe_report(name: &str, report: &ApplyReport) -> String {
+    let mut msg = format!("Applied \"{}\", {} mod(s) changed", name, report.changed.len());
+    if !report.missing.is_empty() {
+        msg.push_str(&format!(". Not installed anymore: {}", report.missing.join(", ")));
+    }
+    msg
+}
+
+async fn list_profiles() -> (Vec<Profile>, Vec<LoadError>) {
+    util::run_blocking(|| match Profile::dir() {
+        Some(dir) => Profile::list(&dir),
+        None => (Vec::new(), Vec::new()),
+    }).await
+}
+
+async fn save_profile(name: String, game_dir: PathBuf, catalog: ModCatalog, dir: PathBuf) -> Result<PathBuf, String> {
+    util::run_blocking(move || Profile::capture(&name, &game_dir, &catalog)?.save(&dir)).await
+}
+
+fn manifest_filter() -> FileFilter {
+    FileFilter::new("M4 manifests")
+        .glob("*.toml")
+        .glob("*.json")
+}
+
+async fn choose_export_path() -> Option<PathBuf> {
+    let connection = zbus::Connection::session().await.ok()?;
+    let proxy = FileChooserProxy::new(&connection).await.ok()?;
+    let files = proxy.save_file(&WindowIdentifier::None, "Export mod setup",
+                                SaveFileOptions::default()
+                                    .accept_label("Export")
+                                    .current_name("m4-manifest.toml")
+                                    .add_filter(manifest_filter()),
+    ).await.ok()?;
+    Url::parse(files.uris().first()?).ok()?.to_file_path().ok()
+}
+
+async fn choose_manifest() -> Option<PathBuf> {
+    let connection = zbus::Connection::session().await.ok()?;
+    let proxy = FileChooserProxy::new(&connection).await.ok()?;
+    let files = proxy.open_file(&WindowIdentifier::None, "Import mod setup",
+                                OpenFileOptions::default()
+                                    .accept_label("Import")
+                                    .add_filter(manifest_filter()),
+    ).await.ok()?;
+    Url::parse(files.uris().first()?).ok()?.to_file_path().ok()
+}
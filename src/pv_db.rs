@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use crate::file_index::ModFile;
+use crate::mod_catalog::ModCatalog;
+
+//PV database, the songs a mod adds
+//Song packs list their songs in rom/mod_pv_db.txt, one `pv_<id>.<field>=<value>` per line.
+//DivaModLoader merges every enabled mod's file into the game's database instead of replacing it,
+//so two mods using the same ID end up overwriting each other's songs.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PvEntry {
+    pub id: u32,
+    pub song_name: Option<String>,
+    pub song_name_en: Option<String>,
+}
+
+impl PvEntry {
+    // The English name if there is one, most people using M4 can't read the Japanese one.
+    pub fn name(&self) -> String {
+        match (&self.song_name_en, &self.song_name) {
+            (Some(name), _) | (None, Some(name)) => name.clone(),
+            (None, None) => format!("pv_{:03}", self.id),
+        }
+    }
+}
+
+// Every song in a pv_db file, sorted by ID.
+// Lines that aren't a `pv_<id>.` key are skipped, the game ignores them too.
+pub fn parse(source: &str) -> Vec<PvEntry> {
+    let mut entries: BTreeMap<u32, PvEntry> = BTreeMap::new();
+    for line in source.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let (id, field) = match key.trim().strip_prefix("pv_").and_then(|k| k.split_once('.')) {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let id: u32 = match id.parse() {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+
+        let entry = entries.entry(id).or_insert(PvEntry { id, song_name: None, song_name_en: None });
+        let value = value.trim();
+        match field {
+            "song_name" if !value.is_empty() => entry.song_name = Some(value.to_string()),
+            "song_name_en" if !value.is_empty() => entry.song_name_en = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    entries.into_values().collect()
+}
+
+// mod_pv_db.txt right inside one of the mod's rom folders.
+pub fn is_pv_db(file: &ModFile) -> bool {
+    let key = file.key();
+    match key.split_once('/') {
+        Some((_, name)) => name == "mod_pv_db.txt",
+        None => false,
+    }
+}
+
+// The songs from all of a mod's pv_db files, a file we can't read counts as empty.
+// When two of them have the same ID the first one is kept.
+pub fn index_mod(files: &[ModFile]) -> Vec<PvEntry> {
+    let mut entries: BTreeMap<u32, PvEntry> = BTreeMap::new();
+    for file in files.iter().filter(|f| is_pv_db(f)) {
+        for entry in read(&file.source) {
+            entries.entry(entry.id).or_insert(entry);
+        }
+    }
+    entries.into_values().collect()
+}
+
+fn read(path: &Path) -> Vec<PvEntry> {
+    match fs::read(path) {
+        //Older packs are sometimes saved as Shift-JIS, the IDs still parse
+        Ok(bytes) => parse(&String::from_utf8_lossy(&bytes)),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PvSong {
+    // Folder name of the mod
+    pub folder: String,
+    pub name: String,
+}
+
+// An ID used by more than one enabled mod.
+#[derive(Debug, Clone)]
+pub struct PvCollision {
+    pub id: u32,
+    // In load order, highest priority first
+    pub songs: Vec<PvSong>,
+}
+
+// `order` is the load order, highest priority first. Disabled mods are skipped.
+pub fn collisions(order: &[String], catalog: &ModCatalog) -> Vec<PvCollision> {
+    let mut ids: BTreeMap<u32, Vec<PvSong>> = BTreeMap::new();
+    for entry in order.iter().filter_map(|folder| catalog.find(folder)) {
+        if !entry.config.enabled {
+            continue;
+        }
+        for pv in &entry.pvs {
+            ids.entry(pv.id).or_default().push(PvSong { folder: entry.folder_name(), name: pv.name() });
+        }
+    }
+    ids.into_iter()
+        .filter(|(_, songs)| songs.len() > 1)
+        .map(|(id, songs)| PvCollision { id, songs })
+        .collect()
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{constants, util};
+
+//Steam, finds the Mega Mix+ install from Steam's own library files
+//Steam keeps a list of its library folders in libraryfolders.vdf,
+//and every installed game has an appmanifest_<appid>.acf in its library with the folder it's installed to.
+
+pub const MEGAMIX_APP_ID: &str = "1761390";
+// The folder Steam installs the game to, for when there's no manifest to ask
+pub const MEGAMIX_INSTALL_DIR: &str = "Hatsune Miku Project DIVA Mega Mix Plus";
+
+//region VDF
+// Valve's KeyValues text format, only what's needed to read the library files.
+#[derive(Debug, Clone)]
+pub enum Vdf {
+    Str(String),
+    Obj(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    pub fn parse(source: &str) -> Option<Vdf> {
+        let tokens = tokenize(source);
+        let mut pos = 0;
+        let mut root = Vec::new();
+        while pos < tokens.len() {
+            let (key, value) = parse_pair(&tokens, &mut pos)?;
+            root.push((key, value));
+        }
+        Some(Vdf::Obj(root))
+    }
+
+    // Keys are case insensitive in VDF files.
+    pub fn get(&self, key: &str) -> Option<&Vdf> {
+        match self {
+            Vdf::Obj(pairs) => pairs.iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v),
+            Vdf::Str(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Vdf::Str(s) => Some(s),
+            Vdf::Obj(_) => None,
+        }
+    }
+
+    pub fn children(&self) -> &[(String, Vdf)] {
+        match self {
+            Vdf::Obj(pairs) => pairs,
+            Vdf::Str(_) => &[],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Str(String),
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => {
+                let mut s = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(other) => s.push(other),
+                            None => break,
+                        },
+                        c => s.push(c),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                //Unquoted token, ends at whitespace or a brace
+                let mut s = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '{' || next == '}' || next == '"' {
+                        break;
+                    }
+                    s.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Str(s));
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_pair(tokens: &[Token], pos: &mut usize) -> Option<(String, Vdf)> {
+    let key = match tokens.get(*pos)? {
+        Token::Str(key) => key.clone(),
+        _ => return None,
+    };
+    *pos += 1;
+
+    match tokens.get(*pos)? {
+        Token::Str(value) => {
+            *pos += 1;
+            Some((key, Vdf::Str(value.clone())))
+        }
+        Token::Open => {
+            *pos += 1;
+            let mut children = Vec::new();
+            loop {
+                if *tokens.get(*pos)? == Token::Close {
+                    *pos += 1;
+                    break;
+                }
+                children.push(parse_pair(tokens, pos)?);
+            }
+            Some((key, Vdf::Obj(children)))
+        }
+        Token::Close => None,
+    }
+}
+//endregion
+
+// Every place Steam can live on Linux, native and Flatpak.
+pub fn steam_roots() -> Vec<PathBuf> {
+    let home = match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home),
+        None => return Vec::new(),
+    };
+
+    let candidates = [
+        home.join(".steam/steam"),
+        home.join(".steam/root"),
+        home.join(".local/share/Steam"),
+        home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+        home.join(".var/app/com.valvesoftware.Steam/data/Steam"),
+    ];
+
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for candidate in candidates {
+        //~/.steam/steam is usually a symlink to one of the others
+        let resolved = fs::canonicalize(&candidate).unwrap_or(candidate);
+        if resolved.join("steamapps").is_dir() && !roots.contains(&resolved) {
+            roots.push(resolved);
+        }
+    }
+    roots
+}
+
+// The library folders of a Steam install, the Steam folder itself is always one of them.
+pub fn library_folders(steam_root: &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![steam_root.to_path_buf()];
+
+    let vdf = fs::read_to_string(steam_root.join("steamapps/libraryfolders.vdf"))
+        .ok()
+        .and_then(|s| Vdf::parse(&s));
+    let folders = match vdf.as_ref().and_then(|v| v.get("libraryfolders")) {
+        Some(folders) => folders,
+        None => return libraries,
+    };
+
+    for (_, folder) in folders.children() {
+        //New format: "0" { "path" "/mnt/games" ... }, old format: "1" "/mnt/games"
+        let path = match folder {
+            Vdf::Obj(_) => folder.get("path").and_then(|p| p.as_str()),
+            Vdf::Str(path) => Some(path.as_str()),
+        };
+        if let Some(path) = path {
+            let path = PathBuf::from(path);
+            if path.is_dir() && !libraries.contains(&path) {
+                libraries.push(path);
+            }
+        }
+    }
+    libraries
+}
+
+// Where the game with `app_id` is installed in `library`, if it is.
+pub fn app_install_dir(library: &Path, app_id: &str) -> Option<PathBuf> {
+    let manifest = library.join("steamapps").join(format!("appmanifest_{}.acf", app_id));
+    let vdf = Vdf::parse(&fs::read_to_string(manifest).ok()?)?;
+    let install_dir = vdf.get("AppState")?.get("installdir")?.as_str()?;
+    Some(library.join("steamapps/common").join(install_dir))
+}
+
+// Looks through every Steam library for Mega Mix+.
+pub fn find_megamix_dir() -> Option<PathBuf> {
+    steam_roots().iter()
+        .flat_map(|root| library_folders(root))
+        .filter_map(|library| app_install_dir(&library, MEGAMIX_APP_ID))
+        .find(|dir| dir.join(constants::MEGAMIX_EXE).is_file())
+}
+
+// The game folder in the Steam library at `path`, for when someone picks the library instead of the game.
+// `path` can be the library itself or its steamapps or steamapps/common folder.
+pub fn megamix_in_library(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase());
+    let library = match name.as_deref() {
+        Some("steamapps") => path.parent()?,
+        Some("common") if path.parent().and_then(|p| p.file_name()).is_some_and(|n| n.eq_ignore_ascii_case("steamapps")) => path.parent()?.parent()?,
+        _ => path,
+    };
+    app_install_dir(library, MEGAMIX_APP_ID)
+        .into_iter()
+        .chain(std::iter::once(library.join("steamapps/common").join(MEGAMIX_INSTALL_DIR)))
+        .find(|dir| dir.join(constants::MEGAMIX_EXE).is_file())
+}
+
+pub async fn detect_megamix_dir() -> Option<String> {
+    util::run_blocking(|| find_megamix_dir().map(|dir| dir.to_string_lossy().to_string())).await
+}
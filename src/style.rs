@@ -51,7 +51,7 @@ impl text_input::StyleSheet for M4InputStyleSheet {
     }
 
     fn value_color(&self) -> Color {
-        return if self.show_validity {
+        if self.show_validity {
             if self.is_valid {
                 Color::from_rgb(0., 0.6, 0.)
             } else {
@@ -59,7 +59,7 @@ impl text_input::StyleSheet for M4InputStyleSheet {
             }
         } else {
             Color::BLACK
-        };
+        }
     }
 
     fn selection_color(&self) -> Color {
@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::Path;
+use toml_edit::{Array, Document, Item, Table, Value};
+use crate::util;
+
+//Edits TOML files in place instead of re-serializing them.
+//Mod and loader configs are written by people, they have comments, their own key order
+//and settings M4 doesn't know about, so only the keys we touch are allowed to change.
+
+// Sets a top level key, keeping the whitespace and comment that was around the old value.
+// If the key doesn't exist yet it gets added after the other top level keys.
+pub fn set_value(doc: &mut Document, key: &str, value: impl Into<Value>) {
+    let mut value = value.into();
+    match doc.get_mut(key) {
+        Some(Item::Value(old)) => {
+            if let (Value::Array(old_arr), Value::Array(new_arr)) = (&*old, &mut value) {
+                match_array_layout(old_arr, new_arr);
+            }
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        _ => {
+            doc.insert(key, Item::Value(value));
+        }
+    }
+}
+
+// Makes a replacement array look like the one it replaces,
+// so a priority list written one entry per line stays one entry per line.
+fn match_array_layout(old: &Array, new: &mut Array) {
+    let mut first_prefix = None;
+    let mut prefix = None;
+    let mut suffix = None;
+    for (i, v) in old.iter().enumerate() {
+        if i == 0 {
+            first_prefix = v.decor().prefix().cloned();
+        } else if prefix.is_none() {
+            prefix = v.decor().prefix().cloned();
+        }
+        if suffix.is_none() {
+            suffix = v.decor().suffix().cloned();
+        }
+    }
+    //With one entry there's nothing to copy the spacing between entries from
+    let prefix = prefix.or_else(|| match &first_prefix {
+        Some(p) if p.as_str().map(|p| p.contains('\n')).unwrap_or(false) => Some(p.clone()),
+        _ => Some(" ".into()),
+    });
+
+    for (i, v) in new.iter_mut().enumerate() {
+        let p = if i == 0 { first_prefix.clone() } else { prefix.clone() };
+        if let Some(p) = p {
+            v.decor_mut().set_prefix(p);
+        }
+        if let Some(s) = suffix.clone() {
+            v.decor_mut().set_suffix(s);
+        }
+    }
+
+    new.set_trailing(old.trailing().clone());
+    new.set_trailing_comma(old.trailing_comma() && !new.is_empty());
+}
+
+// Copies the values of `old` into `new` for every key both have with the same type,
+// used to keep what the user changed when a mod gets updated.
+// Keys in `skip` (top level only) keep the value from `new`.
+pub fn carry_over_values(new: &mut Document, old: &Document, skip: &[&str]) {
+    carry_over_table(new.as_table_mut(), old.as_table(), skip);
+}
+
+fn carry_over_table(new: &mut Table, old: &Table, skip: &[&str]) {
+    for (key, old_item) in old.iter() {
+        if skip.contains(&key) {
+            continue;
+        }
+        match (new.get_mut(key), old_item) {
+            (Some(Item::Value(new_value)), Item::Value(old_value))
+                if new_value.type_name() == old_value.type_name() => {
+                let decor = new_value.decor().clone();
+                *new_value = old_value.clone();
+                *new_value.decor_mut() = decor;
+            }
+            (Some(Item::Table(new_table)), Item::Table(old_table)) => {
+                carry_over_table(new_table, old_table, &[]);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Adds the top level keys of `from` that `doc` doesn't have yet, the rest of `doc` is left alone.
+// Used to pick up settings a new version of the loader added without touching the user's.
+pub fn add_missing_keys(doc: &mut Document, from: &Document) {
+    for (key, item) in from.iter() {
+        if !doc.contains_key(key) {
+            doc.insert(key, item.clone());
+        }
+    }
+}
+
+// Parses `source`, applies `edit` and gives back the new text.
+pub fn patch_str(source: &str, edit: impl FnOnce(&mut Document)) -> Result<String, String> {
+    //Notepad puts a BOM in front, toml_edit won't parse it and it has to be there again afterwards.
+    let (bom, source) = match source.strip_prefix('\u{feff}') {
+        Some(rest) => ("\u{feff}", rest),
+        None => ("", source),
+    };
+    //toml_edit writes everything back with \n, a lot of mods are made on Windows though.
+    let crlf = source.matches("\r\n").count();
+    let normalized = if crlf > 0 { source.replace("\r\n", "\n") } else { source.to_string() };
+
+    let mut doc = normalized.parse::<Document>().map_err(|e| e.to_string())?;
+    edit(&mut doc);
+
+    let mut patched = doc.to_string();
+    if crlf > 0 && crlf == source.matches('\n').count() {
+        patched = patched.replace('\n', "\r\n");
+    } else if crlf > 0 {
+        patched = restore_line_endings(source, &patched);
+    }
+    Ok(format!("{}{}", bom, patched))
+}
+
+// For files that mix \r\n and \n, gives every line of `patched` the ending it had in `source`.
+// Lines are matched by their text in order, a changed or new line takes the ending of the line it's in place of.
+fn restore_line_endings(source: &str, patched: &str) -> String {
+    fn text(line: &str) -> &str {
+        line.strip_suffix("\r\n").or_else(|| line.strip_suffix('\n')).unwrap_or(line)
+    }
+    fn ending(line: &str) -> &'static str {
+        if line.ends_with("\r\n") { "\r\n" } else { "\n" }
+    }
+    let source_lines: Vec<&str> = source.split_inclusive('\n').collect();
+
+    let mut cursor = 0;
+    let mut restored = String::with_capacity(patched.len() + source_lines.len());
+    for line in patched.split_inclusive('\n') {
+        let content = match line.strip_suffix('\n') {
+            Some(content) => content,
+            //The last line, without an ending
+            None => {
+                restored.push_str(line);
+                continue;
+            }
+        };
+        let line_ending = match source_lines[cursor..].iter().position(|l| text(l) == content) {
+            Some(i) => {
+                cursor += i + 1;
+                ending(source_lines[cursor - 1])
+            }
+            None => source_lines.get(cursor).map(|l| ending(l)).unwrap_or("\n"),
+        };
+        restored.push_str(content);
+        restored.push_str(line_ending);
+    }
+    restored
+}
+
+pub fn patch_file(path: &Path, edit: impl FnOnce(&mut Document)) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let patched = patch_str(&source, edit)?;
+    if patched == source {
+        return Ok(());
+    }
+    util::write_atomic(path, patched.as_bytes()).map_err(|e| e.to_string())
+}
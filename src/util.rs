@@ -1,31 +1,34 @@
-use std::fs::File;
-use std::io::Read;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use iced::futures::channel::oneshot;
 use serde::Deserialize;
-use crate::constants;
-use crate::m4_settings::{LoadError, LoadErrorType};
+use crate::{constants, steam};
+use crate::loader_conf::LoaderConfig;
+use crate::m4_settings::LoadError;
 
+// Where settings.toml lives, the directory is created if needed.
+// None when there's no config directory and one can't be made, e.g HOME isn't set.
 #[cfg(target_os = "linux")]
 pub fn get_config_path() -> Option<PathBuf> {
-    let xdg_dirs = xdg::BaseDirectories::with_prefix(constants::APPNAME).unwrap();
-    let setting_file = xdg_dirs.place_config_file(constants::SETTINGS_FILE).expect("Cannot create config file");
-    Some(setting_file)
+    let xdg_dirs = xdg::BaseDirectories::with_prefix(constants::APPNAME).ok()?;
+    xdg_dirs.place_config_file(constants::SETTINGS_FILE).ok()
 }
 
 #[cfg(target_os = "windows")]
 pub fn get_config_path() -> Option<PathBuf> {
-    //%AppData%\VrMote\settings.toml
-    let mut path = std::env::var("AppData").expect("Cannot get APPDATA environment variable");
-    path.push_str("\\");
-    path.push_str(constants::APPNAME);
+    //%AppData%\M4\settings.toml
+    let path = PathBuf::from(std::env::var_os("AppData")?).join(constants::APPNAME);
     //Create the directory if it doesn't exist
-    std::fs::create_dir_all(&path).expect("Cannot create config directory");
-
-    //Now add the file name
-    path.push_str("\\");
-    path.push_str(constants::SETTINGS_FILE);
-    println!("Config path: {}", path);
-    Some(PathBuf::from(path))
+    std::fs::create_dir_all(&path).ok()?;
+    Some(path.join(constants::SETTINGS_FILE))
 }
 
 #[cfg(target_family = "wasm")]
@@ -34,44 +37,192 @@ pub fn get_config_path() -> Option<PathBuf> {
     Some(path)
 }
 
-pub fn is_valid_megamix_dir(path: &Path) -> bool {
+// Why a folder can't be used as the game directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameDirProblem {
+    NotFound,
+    NotADirectory,
+    MissingExe,
+    // A Steam library was picked instead of the game, holds the game folder inside it
+    SteamLibrary(PathBuf),
+    // Holds the mods folder
+    ModsNotWritable(PathBuf),
+}
+
+impl Display for GameDirProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GameDirProblem::NotFound => write!(f, "This folder does not exist"),
+            GameDirProblem::NotADirectory => write!(f, "This is a file, not a folder"),
+            GameDirProblem::MissingExe => write!(f, "There is no {} in this folder", constants::MEGAMIX_EXE),
+            GameDirProblem::SteamLibrary(_) => write!(f, "This looks like a Steam library, not the game folder"),
+            GameDirProblem::ModsNotWritable(mods) => write!(f, "The mods folder {} cannot be written to", mods.display()),
+        }
+    }
+}
+
+impl GameDirProblem {
+    // What to do about it, to show along with the problem.
+    pub fn suggestion(&self) -> String {
+        match self {
+            GameDirProblem::NotFound => String::from("Check the path for typos."),
+            GameDirProblem::NotADirectory => String::from("Pick the folder the game is in rather than a file inside it."),
+            GameDirProblem::MissingExe => String::from("In Steam, right click Mega Mix+ and use Manage > Browse local files to find the game folder."),
+            GameDirProblem::SteamLibrary(game) => format!("Use {} instead.", game.display()),
+            GameDirProblem::ModsNotWritable(_) => String::from("Check the folder's permissions, or move the game out of a read only location."),
+        }
+    }
+}
+
+// Checks that `path` is a Mega Mix+ install.
+// Nothing is written, so this is fine to run whenever, see `check_mods_writable` for that.
+pub fn check_megamix_dir(path: &Path) -> Result<(), GameDirProblem> {
     if !path.exists() {
-        return false;
+        return Err(GameDirProblem::NotFound);
     }
+    if !path.is_dir() {
+        return Err(GameDirProblem::NotADirectory);
+    }
+    if !path.join(constants::MEGAMIX_EXE).is_file() {
+        return Err(match steam::megamix_in_library(path) {
+            Some(game) => GameDirProblem::SteamLibrary(game),
+            None => GameDirProblem::MissingExe,
+        });
+    }
+    Ok(())
+}
 
-    if !path.to_path_buf().join("DivaMegaMix.exe").exists() {
-        return false;
+// Checks that mods can be installed and toggled in the game at `game_dir`.
+// This writes a file to find out, so it's only done before actually changing something.
+pub fn check_mods_writable(game_dir: &Path) -> Result<(), GameDirProblem> {
+    //A mods folder that isn't there yet gets created in the game folder, so that has to be writable instead
+    let mods = LoaderConfig::load(game_dir)
+        .map(|c| c.mods_dir(game_dir))
+        .unwrap_or_else(|_| game_dir.join(constants::MODS_DIR));
+    let target = if mods.is_dir() { mods.as_path() } else { game_dir };
+    if !is_writable(target) {
+        return Err(GameDirProblem::ModsNotWritable(mods));
     }
+    Ok(())
+}
 
-    return true;
+// Permission bits don't tell the whole story (ACLs, read only mounts), so try it.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(".m4-write-test");
+    match File::create(&probe) {
+        Ok(_) => fs::remove_file(&probe).is_ok(),
+        Err(_) => false,
+    }
 }
 
 pub fn has_mod_loader(path: &Path) -> bool {
-    if !is_valid_megamix_dir(path) {
+    if !path.join(constants::MEGAMIX_EXE).is_file() {
         return false;
     }
 
-    if !path.to_path_buf().join("dinput8.dll").exists() {
+    if !path.to_path_buf().join(constants::LOADER_DLL).exists() {
         return false;
     }
 
-    return true;
+    true
 }
 
 pub async fn load_file_into<T: for<'a> Deserialize<'a>>(path: PathBuf) -> Result<T, LoadError> {
-    if !Path::new(&path).exists() {
-        return Err(LoadError(LoadErrorType::FileNotFound));
+    let buffer = fs::read_to_string(&path).map_err(|e| LoadError::read(e, &path))?;
+    toml::from_str(&buffer).map_err(|e| LoadError::parse(e, &path))
+}
+
+// Runs `work` on its own thread, walking folders or unpacking archives on the
+// GUI's executor would freeze the window until it's done.
+pub async fn run_blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+    match receiver.await {
+        Ok(value) => value,
+        //The sender is only dropped without sending when `work` panicked
+        Err(_) => panic!("Background work panicked"),
+    }
+}
+
+// Where the previous version of a file is kept after `write_atomic` replaces it.
+pub fn backup_path(path: &Path) -> PathBuf {
+    append_to_name(path, ".bak")
+}
+
+// `path` with `suffix` added to the whole file name, unlike with_extension which cuts "v1.2" at the dot.
+pub(crate) fn append_to_name(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+// Replaces the file at `path` with `data` without ever leaving a half written file behind.
+// See `write_atomic_with`.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    write_atomic_with(path, |file| file.write_all(data))
+}
+
+// `write_atomic` without the .bak, for files the user picked the location of,
+// e.g. an export, where a stray backup next to it would just be clutter.
+pub fn write_atomic_no_backup(path: &Path, data: &[u8]) -> io::Result<()> {
+    write_atomic_no_backup_with(path, |file| file.write_all(data))
+}
+
+pub fn write_atomic_no_backup_with(path: &Path, write: impl FnOnce(&mut File) -> io::Result<()>) -> io::Result<()> {
+    replace_atomic(path, false, write)
+}
+
+// Every file M4 writes goes through here.
+// The new contents go to a temp file next to `path` first, which is synced to disk
+// and renamed over `path` only if `write` succeeded, so a crash or a full disk
+// leaves either the old file or the new one, never an empty one.
+// The old file is kept as <name>.bak.
+pub fn write_atomic_with(path: &Path, write: impl FnOnce(&mut File) -> io::Result<()>) -> io::Result<()> {
+    replace_atomic(path, true, write)
+}
+
+fn replace_atomic(path: &Path, backup: bool, write: impl FnOnce(&mut File) -> io::Result<()>) -> io::Result<()> {
+    //Two writes to the same file can run at once (e.g. a quick double toggle), each gets its own temp file
+    static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let temp = append_to_name(path, &format!(".m4-tmp-{}-{}", process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+    let res = File::create(&temp).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    if let Err(err) = res {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
     }
 
-    let conf_file = File::open(&path);
-    if conf_file.is_err() {
-        return Err(LoadError(LoadErrorType::CantOpenFile));
+    if backup && path.is_file() {
+        if let Err(err) = write_backup(path) {
+            let _ = fs::remove_file(&temp);
+            return Err(err);
+        }
     }
 
-    let mut buffer = String::new();
-    conf_file.unwrap().read_to_string(&mut buffer).expect("Failed to read file");
+    if let Err(err) = fs::rename(&temp, path) {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
 
-    let settings: T = toml::from_str(&buffer).expect("Failed to parse settings");
+    //The rename only sticks once the directory is synced too
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
 
-    Ok(settings)
-}
\ No newline at end of file
+// Copies `path` to its .bak, synced like the temp file so it's complete before the original is replaced.
+fn write_backup(path: &Path) -> io::Result<()> {
+    let backup = backup_path(path);
+    fs::copy(path, &backup)?;
+    OpenOptions::new().write(true).open(&backup)?.sync_all()
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use m4::mod_conf::ModConfig;
+use m4::toml_patch::{patch_file, set_value};
+use m4::util::{backup_path, write_atomic, write_atomic_with};
+use common::TestDir;
+
+fn leftover_files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn writes_a_new_file_without_backup() {
+    let dir = TestDir::new("new");
+    let path = dir.join("settings.toml");
+
+    write_atomic(&path, b"game_dir = \"/games/mm\"\n").unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "game_dir = \"/games/mm\"\n");
+    assert_eq!(leftover_files(&dir), vec!["settings.toml"]);
+}
+
+#[test]
+fn keeps_the_previous_version_as_bak() {
+    let dir = TestDir::new("bak");
+    let path = dir.join("settings.toml");
+    fs::write(&path, "old").unwrap();
+
+    write_atomic(&path, b"new").unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "old");
+    assert_eq!(leftover_files(&dir), vec!["settings.toml", "settings.toml.bak"]);
+}
+
+#[test]
+fn failing_mid_write_keeps_the_old_file() {
+    let dir = TestDir::new("mid-write");
+    let path = dir.join("config.toml");
+    fs::write(&path, "enabled = true\n").unwrap();
+
+    //Half the file makes it out, then the disk fills up
+    let res = write_atomic_with(&path, |file| {
+        file.write_all(b"enabled = fa")?;
+        Err(io::Error::other("No space left on device"))
+    });
+
+    assert!(res.is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "enabled = true\n");
+    assert_eq!(leftover_files(&dir), vec!["config.toml"]);
+}
+
+#[test]
+fn failing_before_anything_is_written_creates_nothing() {
+    let dir = TestDir::new("no-file");
+    let path = dir.join("settings.toml");
+
+    let res = write_atomic_with(&path, |_| Err(io::Error::other("interrupted")));
+
+    assert!(res.is_err());
+    assert!(leftover_files(&dir).is_empty());
+}
+
+#[test]
+fn writes_at_the_same_time_dont_mix() {
+    let dir = TestDir::new("concurrent");
+    let path = dir.join("config.toml");
+    fs::write(&path, "enabled = true\n").unwrap();
+
+    let writers: Vec<_> = ["enabled = false\n", "enabled = true\n"].into_iter().map(|data| {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            for _ in 0..50 {
+                write_atomic(&path, data.as_bytes()).unwrap();
+            }
+        })
+    }).collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents == "enabled = false\n" || contents == "enabled = true\n", "{:?}", contents);
+    assert_eq!(leftover_files(&dir), vec!["config.toml", "config.toml.bak"]);
+}
+
+#[test]
+fn missing_directory_is_an_error() {
+    let dir = TestDir::new("missing-dir");
+    let path = dir.join("not-here").join("settings.toml");
+
+    assert!(write_atomic(&path, b"data").is_err());
+    assert!(leftover_files(&dir).is_empty());
+}
+
+#[test]
+fn patching_a_mod_config_keeps_a_backup() {
+    let dir = TestDir::new("patch");
+    let path = dir.join("config.toml");
+    fs::write(&path, "enabled = true # on\nname = \"Mod\"\n").unwrap();
+
+    patch_file(&path, |doc| set_value(doc, "enabled", false)).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "enabled = false # on\nname = \"Mod\"\n");
+    assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "enabled = true # on\nname = \"Mod\"\n");
+    let config: ModConfig = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert!(!config.enabled);
+}
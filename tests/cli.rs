@@ -0,0 +1,282 @@
+mod common;
+
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use clap::Parser;
+use m4::cli::{names_subcommand, run_to, Cli};
+use m4::loader_conf::LoaderConfig;
+use m4::mod_conf::ModConfig;
+use serde_json::{json, Value};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+use common::TestDir;
+
+// Profiles live in the config directory, point it somewhere only these tests use.
+// Every test shares it, so profile names have to be unique within this file.
+fn config_home() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("m4-test-cli-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+    });
+}
+
+// A game directory with mods Alpha (on) and Beta (off), loaded in that order.
+fn game(name: &str) -> TestDir {
+    let dir = TestDir::new(name);
+    fs::write(dir.join("DivaMegaMix.exe"), "").unwrap();
+    fs::write(dir.join("config.toml"), "enabled = true\nmods = \"mods\"\npriority = [\"Alpha\", \"Beta\"]\n").unwrap();
+    for (folder, config) in [
+        ("Alpha", "enabled = true\nname = \"Alpha Mod\"\nversion = \"1.0\"\nauthor = \"Someone\"\n"),
+        ("Beta", "enabled = false\n"),
+    ] {
+        let mod_dir = dir.join("mods").join(folder);
+        fs::create_dir_all(&mod_dir).unwrap();
+        fs::write(mod_dir.join("config.toml"), config).unwrap();
+    }
+    dir
+}
+
+// Runs m4 with `args` against the game at `dir`, returns the exit code and what was printed.
+fn m4(dir: &Path, args: &[&str]) -> (i32, String) {
+    config_home();
+    let mut argv = vec!["m4", "--game-dir", dir.to_str().unwrap()];
+    argv.extend_from_slice(args);
+    let mut out = Vec::new();
+    let code = run_to(Cli::try_parse_from(argv).unwrap(), &mut out);
+    (code, String::from_utf8(out).unwrap())
+}
+
+fn m4_json(dir: &Path, args: &[&str]) -> Value {
+    let mut argv = vec!["--json"];
+    argv.extend_from_slice(args);
+    let (code, out) = m4(dir, &argv);
+    assert_eq!(code, 0, "m4 {} failed: {}", args.join(" "), out);
+    serde_json::from_str(&out).unwrap()
+}
+
+fn m4_json_failing(dir: &Path, args: &[&str]) -> Value {
+    let mut argv = vec!["--json"];
+    argv.extend_from_slice(args);
+    let (code, out) = m4(dir, &argv);
+    assert_eq!(code, 1);
+    serde_json::from_str(&out).unwrap()
+}
+
+fn enabled(dir: &Path, folder: &str) -> bool {
+    let config: ModConfig = toml::from_str(&fs::read_to_string(dir.join("mods").join(folder).join("config.toml")).unwrap()).unwrap();
+    config.enabled
+}
+
+fn priority(dir: &Path) -> Vec<String> {
+    LoaderConfig::load(dir).unwrap().priority
+}
+
+fn zip(path: &Path, files: &[(&str, &str)]) -> PathBuf {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    for (name, contents) in files {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+    path.to_path_buf()
+}
+
+#[test]
+fn list_prints_every_mod_as_json() {
+    let dir = game("list");
+    fs::create_dir(dir.join("mods/Broken")).unwrap();
+    fs::write(dir.join("mods/Broken/config.toml"), "enabled = [").unwrap();
+
+    let list = m4_json(&dir, &["list"]);
+
+    assert_eq!(list["loader_enabled"], json!(true));
+    assert_eq!(list["mods_dir"], json!(dir.join("mods")));
+    assert_eq!(list["mods"], json!([
+        {
+            "folder": "Alpha",
+            "name": "Alpha Mod",
+            "author": "Someone",
+            "version": "1.0",
+            "description": null,
+            "enabled": true,
+            "priority": 1,
+        },
+        {
+            "folder": "Beta",
+            "name": "Beta",
+            "author": null,
+            "version": null,
+            "description": null,
+            "enabled": false,
+            "priority": 2,
+        },
+    ]));
+    assert_eq!(list["errors"].as_array().unwrap().len(), 1);
+    assert_eq!(list["errors"][0]["folder"], json!("Broken"));
+}
+
+#[test]
+fn enable_and_disable_write_the_mod_config() {
+    let dir = game("toggle");
+
+    assert_eq!(m4_json(&dir, &["enable", "Beta"]), json!({ "folder": "Beta", "enabled": true }));
+    assert!(enabled(&dir, "Beta"));
+
+    //By the name in its config too
+    assert_eq!(m4(&dir, &["disable", "alpha mod"]).0, 0);
+    assert!(!enabled(&dir, "Alpha"));
+
+    assert_eq!(m4(&dir, &["enable", "Gamma"]).0, 1);
+}
+
+#[test]
+fn errors_are_printed_as_json() {
+    let dir = game("error");
+
+    let error = m4_json_failing(&dir, &["enable", "Gamma"]);
+
+    assert_eq!(error, json!({ "error": "No mod named \"Gamma\"" }));
+}
+
+#[test]
+fn install_unpacks_into_the_mods_folder() {
+    let dir = game("install");
+    let archive = zip(&dir.join("Gamma.zip"), &[("Gamma/config.toml", "enabled = true\n"), ("Gamma/rom/a.txt", "a")]);
+    let archive = archive.to_str().unwrap();
+
+    let installed = m4_json(&dir, &["install", archive]);
+
+    assert_eq!(installed, json!({ "folder": "Gamma", "path": dir.join("mods/Gamma") }));
+    assert_eq!(fs::read_to_string(dir.join("mods/Gamma/rom/a.txt")).unwrap(), "a");
+
+    //Installing it again needs --on-conflict
+    assert_eq!(m4(&dir, &["install", archive]).0, 1);
+    assert_eq!(m4_json(&dir, &["install", archive, "--on-conflict", "keep-both"])["folder"], json!("Gamma (2)"));
+    assert!(dir.join("mods/Gamma (2)/config.toml").is_file());
+}
+
+#[test]
+fn priority_move_saves_the_new_order() {
+    let dir = game("priority");
+
+    assert_eq!(m4_json(&dir, &["priority", "move", "Beta", "1"]), json!(["Beta", "Alpha"]));
+    assert_eq!(priority(&dir), vec!["Beta", "Alpha"]);
+    assert_eq!(m4_json(&dir, &["priority"]), json!(["Beta", "Alpha"]));
+
+    assert_eq!(m4(&dir, &["priority", "move", "Beta", "3"]).0, 1);
+    assert_eq!(priority(&dir), vec!["Beta", "Alpha"]);
+}
+
+#[test]
+fn profiles_are_saved_listed_applied_and_deleted() {
+    let dir = game("profile");
+
+    assert_eq!(m4(&dir, &["profile", "save", "CLI Saved"]).0, 0);
+    let profiles = m4_json(&dir, &["profile"]);
+    let saved = profiles.as_array().unwrap().iter().find(|p| p["name"] == json!("CLI Saved")).unwrap();
+    assert_eq!(saved, &json!({ "name": "CLI Saved", "enabled": 1, "mods": 2 }));
+
+    m4(&dir, &["enable", "Beta"]);
+    m4(&dir, &["priority", "move", "Beta", "1"]);
+    let applied = m4_json(&dir, &["profile", "apply", "cli saved"]);
+    assert_eq!(applied, json!({ "profile": "CLI Saved", "changed": ["Beta"], "missing": [] }));
+    assert!(!enabled(&dir, "Beta"));
+    assert_eq!(priority(&dir), vec!["Alpha", "Beta"]);
+
+    assert_eq!(m4(&dir, &["profile", "delete", "CLI Saved"]).0, 0);
+    let profiles = m4_json(&dir, &["profile"]);
+    assert!(profiles.as_array().unwrap().iter().all(|p| p["name"] != json!("CLI Saved")));
+}
+
+#[test]
+fn export_then_import_restores_the_setup() {
+    let dir = game("manifest");
+    let manifest = dir.join("setup.json");
+    let manifest = manifest.to_str().unwrap();
+
+    assert_eq!(m4(&dir, &["export", manifest]).0, 0);
+    let exported: Value = serde_json::from_str(&fs::read_to_string(manifest).unwrap()).unwrap();
+    assert_eq!(exported["mods"][0]["folder"], json!("Alpha"));
+    //Exporting again over it leaves nothing else next to the file the user picked
+    assert_eq!(m4(&dir, &["export", manifest]).0, 0);
+    let names: Vec<String> = fs::read_dir(&*dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect();
+    assert!(!names.contains(&String::from("setup.json.bak")), "{:?}", names);
+
+    m4(&dir, &["disable", "Alpha"]);
+    m4(&dir, &["priority", "move", "Beta", "1"]);
+    fs::remove_dir_all(dir.join("mods/Beta")).unwrap();
+
+    let dry_run = m4_json(&dir, &["import", manifest, "--dry-run"]);
+    assert_eq!(dry_run, json!({
+        "mods": [
+            {
+                "folder": "Alpha",
+                "name": "Alpha Mod",
+                "status": "installed",
+                "version": "1.0",
+                "local_folder": "Alpha",
+                "local_version": "1.0",
+            },
+            {
+                "folder": "Beta",
+                "name": "Beta",
+                "status": "missing",
+                "version": null,
+                "local_folder": null,
+                "local_version": null,
+            },
+        ],
+        "applied": false,
+        "changed": [],
+    }));
+    assert!(!enabled(&dir, "Alpha"));
+
+    let imported = m4_json(&dir, &["import", manifest]);
+    assert_eq!(imported["applied"], json!(true));
+    assert_eq!(imported["changed"], json!(["Alpha"]));
+    assert!(enabled(&dir, "Alpha"));
+    assert_eq!(priority(&dir)[0], "Alpha");
+}
+
+#[test]
+fn loader_install_keeps_the_config() {
+    let dir = game("loader");
+    let archive = zip(&dir.join("DivaModLoader-v1.2.0.zip"), &[
+        ("DivaModLoader/dinput8.dll", "dll"),
+        ("DivaModLoader/config.toml", "enabled = true\nmods = \"mods\"\n"),
+    ]);
+
+    let installed = m4_json(&dir, &["loader", "install", archive.to_str().unwrap()]);
+
+    assert_eq!(installed, json!({ "version": "1.2.0", "kept_config": true }));
+    assert_eq!(fs::read_to_string(dir.join("dinput8.dll")).unwrap(), "dll");
+    assert_eq!(priority(&dir), vec!["Alpha", "Beta"]);
+    let loader = m4_json(&dir, &["loader"]);
+    assert_eq!(loader["installed"], json!(true));
+    assert_eq!(loader["version"], json!("1.2.0"));
+}
+
+#[test]
+fn a_folder_without_the_game_is_refused() {
+    let dir = TestDir::new("not-a-game");
+
+    let error = m4_json_failing(&dir, &["list"]);
+
+    assert!(error["error"].as_str().unwrap().contains("is not a usable Mega Mix+ directory"));
+}
+
+#[test]
+fn only_arguments_with_a_subcommand_are_for_the_cli() {
+    assert!(names_subcommand(["list", "--bogus"]));
+    assert!(names_subcommand(["--json", "profile", "save"]));
+    //What a launcher might pass along when opening the GUI
+    assert!(!names_subcommand(["file:///home/user/mod.zip"]));
+    assert!(!names_subcommand(["--some-launcher-flag"]));
+    assert!(!names_subcommand(Vec::<String>::new()));
+}
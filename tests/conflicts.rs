@@ -0,0 +1,125 @@
+mod common;
+
+use std::fs;
+use m4::conflicts::Overlay;
+use m4::mod_catalog::ModCatalog;
+use common::TestDir;
+
+// A mods folder with a few mods, `files` are relative to the mod folder.
+fn mods_dir(name: &str, mods: &[(&str, &str, &[&str])]) -> TestDir {
+    let dir = TestDir::new(name);
+    for (folder, config, files) in mods {
+        let mod_dir = dir.join(folder);
+        fs::create_dir_all(&mod_dir).unwrap();
+        fs::write(mod_dir.join("config.toml"), config).unwrap();
+        for file in *files {
+            let path = mod_dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+    }
+    dir
+}
+
+fn order(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+#[test]
+fn higher_priority_mod_wins() {
+    let dir = mods_dir("winner", &[
+        ("A", "enabled = true\n", &["rom/2d/spr_gam_cmn.farc", "readme.txt"]),
+        ("B", "enabled = true\n", &["rom/2d/SPR_GAM_CMN.farc", "rom/2d/other.farc", "readme.txt"]),
+    ]);
+    let catalog = ModCatalog::scan(&dir).unwrap();
+
+    let overlay = Overlay::build(&order(&["B", "A"]), &catalog);
+    let conflicts: Vec<_> = overlay.conflicts().collect();
+
+    //readme.txt is never loaded by the game, so it's not a conflict
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].path, "rom/2d/SPR_GAM_CMN.farc");
+    assert_eq!(conflicts[0].winner(), "B");
+    assert_eq!(conflicts[0].losers(), &[String::from("A")]);
+
+    let overlay = Overlay::build(&order(&["A", "B"]), &catalog);
+    assert_eq!(overlay.conflicts().next().unwrap().winner(), "A");
+}
+
+#[test]
+fn disabled_mods_do_not_conflict() {
+    let dir = mods_dir("disabled", &[
+        ("A", "enabled = true\n", &["rom/a.farc"]),
+        ("B", "enabled = false\n", &["rom/a.farc"]),
+    ]);
+    let catalog = ModCatalog::scan(&dir).unwrap();
+
+    let overlay = Overlay::build(&order(&["A", "B"]), &catalog);
+
+    assert_eq!(overlay.conflicts().count(), 0);
+    assert_eq!(overlay.entries.len(), 1);
+}
+
+#[test]
+fn include_folders_are_layered() {
+    let dir = mods_dir("include", &[
+        ("A", "enabled = true\ninclude = [\"base\", \"extra\"]\n", &["base/rom/a.farc", "extra/rom/b.farc", "rom/ignored.farc"]),
+        ("B", "enabled = true\n", &["rom/b.farc"]),
+    ]);
+    let catalog = ModCatalog::scan(&dir).unwrap();
+
+    let files: Vec<&str> = catalog.find("A").unwrap().files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(files, vec!["rom/a.farc", "rom/b.farc"]);
+
+    let overlay = Overlay::build(&order(&["A", "B"]), &catalog);
+    let conflicts: Vec<_> = overlay.conflicts().collect();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].path, "rom/b.farc");
+}
+
+#[test]
+fn tree_groups_files_by_folder() {
+    let dir = mods_dir("tree", &[
+        ("A", "enabled = true\n", &["rom/2d/a.farc", "rom/rob/b.farc", "rom/mod_pv_db.txt"]),
+        ("B", "enabled = true\n", &["rom/2d/a.farc"]),
+    ]);
+    let catalog = ModCatalog::scan(&dir).unwrap();
+
+    let tree = Overlay::build(&order(&["A", "B"]), &catalog).tree();
+
+    let rom = &tree.children[0];
+    assert_eq!(rom.path, "rom");
+    assert_eq!(rom.file_count(), 3);
+    assert_eq!(rom.conflict_count(), 1);
+    let names: Vec<&str> = rom.children.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["2d", "rob", "mod_pv_db.txt"]);
+    assert_eq!(rom.children[0].children[0].entry.as_ref().unwrap().providers, vec!["A", "B"]);
+}
+
+
+#[test]
+fn only_rom_folders_are_indexed() {
+    let dir = mods_dir("rom", &[
+        ("A", "enabled = true\n", &["rom/a.farc", "ROM_steam/b.farc", "rom_switch_en/c.farc", "rom_notes.txt", "romance.txt", "romfs/d.farc", "readme/rom/e.farc"]),
+    ]);
+    let catalog = ModCatalog::scan(&dir).unwrap();
+
+    let files: Vec<&str> = catalog.find("A").unwrap().files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(files, vec!["ROM_steam/b.farc", "rom/a.farc", "rom_switch_en/c.farc"]);
+}
+
+#[test]
+fn pv_db_files_are_merged() {
+    let dir = mods_dir("merged", &[
+        ("A", "enabled = true\n", &["rom/mod_pv_db.txt"]),
+        ("B", "enabled = true\n", &["rom/mod_pv_db.txt"]),
+    ]);
+    let catalog = ModCatalog::scan(&dir).unwrap();
+    let overlay = Overlay::build(&order(&["A", "B"]), &catalog);
+
+    assert_eq!(overlay.entries.len(), 1);
+    assert!(overlay.entries[0].merged);
+    assert_eq!(overlay.entries[0].providers, vec!["A", "B"]);
+    assert_eq!(overlay.conflicts().count(), 0);
+    assert_eq!(overlay.tree().conflict_count(), 0);
+}
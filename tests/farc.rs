@@ -0,0 +1,185 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use aes::Aes128;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::cipher::generic_array::GenericArray;
+use m4::farc::{ArchiveRef, Farc, FarcErrorType, FarcFormat, ModFarc, FUTURE_TONE_KEY};
+use m4::mod_catalog::{ModCatalog, ModEntry};
+use m4::mod_conf::ModConfig;
+use common::TestDir;
+
+// Signature, header size, then `header`
+fn archive(signature: &[u8], header: &[u8]) -> Vec<u8> {
+    let mut data = signature.to_vec();
+    data.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    data.extend_from_slice(header);
+    data
+}
+
+fn entry(name: &str, fields: &[u32]) -> Vec<u8> {
+    let mut data = name.as_bytes().to_vec();
+    data.push(0);
+    for field in fields {
+        data.extend_from_slice(&field.to_be_bytes());
+    }
+    data
+}
+
+fn header(fields: &[u32], entries: &[Vec<u8>]) -> Vec<u8> {
+    let mut data: Vec<u8> = fields.iter().flat_map(|f| f.to_be_bytes()).collect();
+    for entry in entries {
+        data.extend_from_slice(entry);
+    }
+    data
+}
+
+// A Future Tone archive, `header` is zero padded and encrypted after the IV
+fn encrypted(flags: u32, iv: [u8; 16], header: &[u8]) -> Vec<u8> {
+    let cipher = Aes128::new(&GenericArray::from(FUTURE_TONE_KEY));
+    let mut plain = header.to_vec();
+    plain.resize(header.len().div_ceil(16) * 16, 0);
+    let mut body = iv.to_vec();
+    let mut previous = iv;
+    for chunk in plain.chunks(16) {
+        let mut block = GenericArray::clone_from_slice(chunk);
+        for (b, p) in block.iter_mut().zip(previous) {
+            *b ^= p;
+        }
+        cipher.encrypt_block(&mut block);
+        previous = block.into();
+        body.extend_from_slice(&previous);
+    }
+
+    let mut data = flags.to_be_bytes().to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&body);
+    archive(b"FARC", &data)
+}
+
+fn names(farc: &Farc) -> Vec<&str> {
+    farc.entries.iter().map(|e| e.name.as_str()).collect()
+}
+
+#[test]
+fn reads_plain_archives() {
+    let data = archive(b"FArc", &header(&[0x10], &[entry("spr_a.bin", &[0x40, 100]), entry("spr_b.bin", &[0xb0, 20])]));
+
+    let farc = Farc::parse(&data).unwrap();
+
+    assert_eq!(farc.format, FarcFormat::Plain);
+    assert_eq!(farc.alignment, 0x10);
+    assert_eq!(names(&farc), vec!["spr_a.bin", "spr_b.bin"]);
+    assert_eq!(farc.entries[1].offset, 0xb0);
+    assert_eq!(farc.entries[1].original_size, 20);
+}
+
+#[test]
+fn reads_compressed_archives() {
+    let data = archive(b"FArC", &header(&[0x40], &[entry("obj_a.bin", &[0x40, 30, 100])]));
+
+    let farc = Farc::parse(&data).unwrap();
+
+    assert_eq!(farc.format, FarcFormat::Compressed);
+    assert_eq!((farc.entries[0].size, farc.entries[0].original_size), (30, 100));
+}
+
+#[test]
+fn reads_both_extended_layouts() {
+    //Older layout, entries right after the alignment
+    let old = archive(b"FARC", &header(&[6, 0, 0x10], &[entry("tex_a.bin", &[0x40, 30, 100])]));
+    let farc = Farc::parse(&old).unwrap();
+    assert_eq!(farc.format, FarcFormat::Extended { compressed: true, encrypted: true });
+    assert_eq!(names(&farc), vec!["tex_a.bin"]);
+
+    //Newer layout with a version, entry count, padding and per entry flags
+    let new = archive(b"FARC", &header(&[2, 0, 0x10, 1, 2, 0], &[
+        entry("a.bin", &[0x40, 30, 100, 2]),
+        entry("b.bin", &[0x80, 10, 10, 0]),
+    ]));
+    let farc = Farc::parse(&new).unwrap();
+    assert_eq!(farc.format, FarcFormat::Extended { compressed: true, encrypted: false });
+    assert_eq!(names(&farc), vec!["a.bin", "b.bin"]);
+    assert_eq!(farc.entries[1].offset, 0x80);
+}
+
+#[test]
+fn reads_encrypted_headers() {
+    let iv = [0x3c, 0x8f, 0x9a, 0x1d, 0x52, 0x07, 0xe4, 0x61, 0x9b, 0x20, 0x4d, 0xf3, 0x88, 0x15, 0xc6, 0x7e];
+    let data = encrypted(6, iv, &header(&[0x10, 1, 2, 0], &[
+        entry("spr_sel_pv001.bin", &[0x40, 30, 100, 2]),
+        entry("spr_sel_pv002.bin", &[0x80, 10, 10, 2]),
+    ]));
+
+    let farc = Farc::parse(&data).unwrap();
+
+    assert_eq!(farc.format, FarcFormat::Extended { compressed: true, encrypted: true });
+    assert_eq!(farc.alignment, 0x10);
+    assert_eq!(names(&farc), vec!["spr_sel_pv001.bin", "spr_sel_pv002.bin"]);
+    assert_eq!((farc.entries[1].offset, farc.entries[1].size), (0x80, 10));
+}
+
+#[test]
+fn reports_what_it_cant_read() {
+    let encrypted = archive(b"FARC", &header(&[4, 0, 0x3c8f9a1d], &[vec![0xAB; 32]]));
+    assert_eq!(Farc::parse(&encrypted).unwrap_err().0, FarcErrorType::EncryptedHeader);
+
+    assert_eq!(Farc::parse(b"PK\x03\x04....").unwrap_err().0, FarcErrorType::NotFarc);
+
+    let mut cut = archive(b"FArc", &header(&[0x10], &[entry("spr_a.bin", &[0x40, 100])]));
+    cut.truncate(cut.len() - 3);
+    assert_eq!(Farc::parse(&cut).unwrap_err().0, FarcErrorType::Corrupt);
+}
+
+#[test]
+fn read_only_needs_the_header() {
+    let mut data = archive(b"FArC", &header(&[0x10], &[entry("spr_a.bin", &[0x40, 3, 5])]));
+    data.extend_from_slice(&[0xFF; 64]);
+    let dir = TestDir::new("header");
+    let path = dir.join("a.farc");
+    fs::write(&path, &data).unwrap();
+
+    let farc = Farc::read(&path).unwrap();
+
+    assert_eq!(names(&farc), vec!["spr_a.bin"]);
+}
+
+fn installed(folder: &str, enabled: bool, archives: &[(&str, &[&str])]) -> ModEntry {
+    let archives = archives.iter().map(|(path, entries)| {
+        let entries: Vec<Vec<u8>> = entries.iter().map(|e| entry(e, &[0, 0])).collect();
+        ModFarc {
+            path: path.to_string(),
+            contents: Farc::parse(&archive(b"FArc", &header(&[0x10], &entries))),
+        }
+    }).collect();
+    ModEntry {
+        path: PathBuf::from("/games/mm/mods").join(folder),
+        config: ModConfig { enabled, ..Default::default() },
+        files: Vec::new(),
+        pvs: Vec::new(),
+        archives,
+    }
+}
+
+#[test]
+fn overlapping_entries_between_enabled_mods() {
+    let catalog = ModCatalog {
+        mods: vec![
+            installed("A", true, &[("rom/2d/spr_a.farc", &["spr_a.bin", "SPR_SHARED.bin", "spr_both.bin"])]),
+            installed("B", true, &[("rom/2d/spr_b.farc", &["spr_shared.bin", "spr_both.bin", "spr_b.bin"])]),
+            installed("C", false, &[("rom/2d/spr_a.farc", &["spr_a.bin"])]),
+        ],
+        ..Default::default()
+    };
+    let order: Vec<String> = vec!["B".into(), "A".into(), "C".into()];
+
+    let overlaps = m4::farc::overlaps(&order, &catalog);
+
+    assert_eq!(overlaps.len(), 1);
+    assert_eq!(overlaps[0].archives, vec![
+        ArchiveRef { folder: "B".into(), archive: "rom/2d/spr_b.farc".into() },
+        ArchiveRef { folder: "A".into(), archive: "rom/2d/spr_a.farc".into() },
+    ]);
+    assert_eq!(overlaps[0].entries, vec!["spr_both.bin", "spr_shared.bin"]);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::fs;
+use m4::util::{check_megamix_dir, check_mods_writable, GameDirProblem};
+use common::TestDir;
+
+#[test]
+fn accepts_a_game_folder() {
+    let dir = TestDir::new("valid");
+    fs::write(dir.join("DivaMegaMix.exe"), "").unwrap();
+
+    assert_eq!(check_megamix_dir(&dir), Ok(()));
+    //It's run on every keystroke, so it only looks
+    assert_eq!(fs::read_dir(&*dir).unwrap().count(), 1);
+}
+
+#[test]
+fn tells_missing_paths_and_files_apart() {
+    let dir = TestDir::new("missing");
+    fs::write(dir.join("DivaMegaMix.exe"), "").unwrap();
+
+    assert_eq!(check_megamix_dir(&dir.join("nope")), Err(GameDirProblem::NotFound));
+    assert_eq!(check_megamix_dir(&dir.join("DivaMegaMix.exe")), Err(GameDirProblem::NotADirectory));
+    fs::create_dir(dir.join("other")).unwrap();
+    assert_eq!(check_megamix_dir(&dir.join("other")), Err(GameDirProblem::MissingExe));
+}
+
+#[test]
+fn points_from_the_steam_library_to_the_game() {
+    let library = TestDir::new("library");
+    let game = library.join("steamapps/common/Hatsune Miku Project DIVA Mega Mix Plus");
+    fs::create_dir_all(&game).unwrap();
+    fs::write(game.join("DivaMegaMix.exe"), "").unwrap();
+
+    for picked in [library.to_path_buf(), library.join("steamapps"), library.join("steamapps/common")] {
+        assert_eq!(check_megamix_dir(&picked), Err(GameDirProblem::SteamLibrary(game.clone())));
+    }
+}
+
+#[test]
+fn uses_the_install_dir_from_the_manifest() {
+    let library = TestDir::new("manifest");
+    let game = library.join("steamapps/common/MegaMix");
+    fs::create_dir_all(&game).unwrap();
+    fs::write(game.join("DivaMegaMix.exe"), "").unwrap();
+    fs::write(library.join("steamapps/appmanifest_1761390.acf"),
+              "\"AppState\"\n{\n\t\"appid\"\t\t\"1761390\"\n\t\"installdir\"\t\t\"MegaMix\"\n}\n").unwrap();
+
+    let problem = check_megamix_dir(&library).unwrap_err();
+    assert_eq!(problem, GameDirProblem::SteamLibrary(game.clone()));
+    assert!(problem.suggestion().contains(&game.display().to_string()));
+}
+
+#[cfg(unix)]
+#[test]
+fn reports_a_read_only_mods_folder() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TestDir::new("readonly");
+    fs::write(dir.join("DivaMegaMix.exe"), "").unwrap();
+    fs::write(dir.join("config.toml"), "enabled = true\nmods = \"my mods\"\n").unwrap();
+    let mods = dir.join("my mods");
+    fs::create_dir(&mods).unwrap();
+    fs::set_permissions(&mods, fs::Permissions::from_mode(0o555)).unwrap();
+
+    //Root can write anywhere, nothing to check then
+    let writable = fs::write(mods.join("probe"), "").is_ok();
+    let res = check_mods_writable(&dir);
+    //Still a game folder, it just can't be changed
+    let game = check_megamix_dir(&dir);
+    fs::set_permissions(&mods, fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(game, Ok(()));
+    if !writable {
+        assert_eq!(res, Err(GameDirProblem::ModsNotWritable(mods)));
+    }
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use m4::installer::{install_archive, ArchiveKind, ConflictAction, InstallErrorType};
+use m4::mod_catalog::ModCatalog;
+use m4::mod_conf::ModConfig;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+use common::TestDir;
+
+// A zip with `files` in it, names are written as given.
+fn zip(dir: &Path, name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let path = dir.join(name);
+    let mut zip = ZipWriter::new(File::create(&path).unwrap());
+    for (name, contents) in files {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+    path
+}
+
+fn install_error(archive: &Path, mods: &Path) -> InstallErrorType {
+    install_archive(archive, mods, None).unwrap_err().0
+}
+
+#[test]
+fn entries_outside_the_mod_folder_are_refused() {
+    let dir = TestDir::new("unsafe");
+    let mods = dir.join("mods");
+
+    for (i, name) in ["../x", "Mod/../../x", "/abs", "C:/x", "C:\\Windows\\x"].iter().enumerate() {
+        let archive = zip(&dir, &format!("{}.zip", i), &[("Mod/config.toml", "enabled = true\n"), (name, "")]);
+        assert!(matches!(install_error(&archive, &mods), InstallErrorType::UnsafePath(_)), "{} was let through", name);
+    }
+    assert!(!dir.join("x").exists());
+    assert!(!mods.exists());
+}
+
+#[test]
+fn the_folder_with_config_toml_is_installed() {
+    let dir = TestDir::new("nested");
+    let mods = dir.join("mods");
+    let one_deep = zip(&dir, "one.zip", &[
+        ("Song Pack/config.toml", "enabled = true\n"),
+        ("Song Pack/rom/mod_pv_db.txt", ""),
+    ]);
+    let two_deep = zip(&dir, "two.zip", &[
+        ("Other v1.2/readme.txt", ""),
+        ("Other v1.2/Other/config.toml", "enabled = true\n"),
+        ("Other v1.2/Other/rom/2d/a.farc", ""),
+    ]);
+
+    let installed = install_archive(&one_deep, &mods, None).unwrap();
+    assert_eq!(installed.folder, "Song Pack");
+    assert!(mods.join("Song Pack/rom/mod_pv_db.txt").is_file());
+
+    let installed = install_archive(&two_deep, &mods, None).unwrap();
+    assert_eq!(installed.folder, "Other");
+    assert!(mods.join("Other/rom/2d/a.farc").is_file());
+    //Only the mod folder, not what's packed around it
+    assert!(!mods.join("Other/readme.txt").exists());
+    assert!(!mods.join("Other v1.2").exists());
+}
+
+#[test]
+fn config_toml_in_any_case_is_installed_as_config_toml() {
+    let dir = TestDir::new("case");
+    let mods = dir.join("mods");
+    let archive = zip(&dir, "shouty.zip", &[("Shouty/Config.toml", "enabled = true\nname = \"Shouty\"\n")]);
+
+    install_archive(&archive, &mods, None).unwrap();
+
+    let catalog = ModCatalog::scan(&mods).unwrap();
+    assert!(catalog.errors.is_empty());
+    assert_eq!(catalog.mods[0].display_name(), "Shouty");
+}
+
+#[test]
+fn config_toml_at_the_top_uses_the_archive_name() {
+    let dir = TestDir::new("top");
+    let mods = dir.join("mods");
+    let archive = zip(&dir, "Top Mod.zip", &[("config.toml", "enabled = true\n"), ("rom/a.farc", "")]);
+
+    let installed = install_archive(&archive, &mods, None).unwrap();
+
+    assert_eq!(installed.folder, "Top Mod");
+    assert!(mods.join("Top Mod/rom/a.farc").is_file());
+}
+
+#[test]
+fn an_archive_without_config_toml_is_not_a_mod() {
+    let dir = TestDir::new("noconfig");
+    let mods = dir.join("mods");
+    let archive = zip(&dir, "pack.zip", &[("Pack/rom/a.farc", ""), ("Pack/readme.txt", "")]);
+
+    let err = install_archive(&archive, &mods, None).unwrap_err();
+
+    assert!(matches!(err.0, InstallErrorType::NoModConfig));
+    assert!(err.to_string().contains("config.toml"));
+    assert!(!mods.exists() || fs::read_dir(&mods).unwrap().next().is_none());
+}
+
+#[test]
+fn archive_type_comes_from_the_contents_not_the_extension() {
+    let dir = TestDir::new("detect");
+    let zip_as_rar = zip(&dir, "mod.rar", &[("Mod/config.toml", "enabled = true\n")]);
+    let seven_z_as_zip = dir.join("mod.zip");
+    fs::write(&seven_z_as_zip, b"7z\xBC\xAF\x27\x1C\x00\x04").unwrap();
+    let rar_as_7z = dir.join("mod.7z");
+    fs::write(&rar_as_7z, b"Rar!\x1A\x07\x01\x00").unwrap();
+    let text_as_zip = dir.join("readme.zip");
+    fs::write(&text_as_zip, "not an archive").unwrap();
+
+    assert_eq!(ArchiveKind::detect(&zip_as_rar).unwrap(), ArchiveKind::Zip);
+    assert_eq!(ArchiveKind::detect(&seven_z_as_zip).unwrap(), ArchiveKind::SevenZ);
+    assert_eq!(ArchiveKind::detect(&rar_as_7z).unwrap(), ArchiveKind::Rar);
+    assert!(matches!(ArchiveKind::detect(&text_as_zip).unwrap_err().0, InstallErrorType::UnsupportedArchive));
+
+    //And a misnamed zip installs like any other
+    let installed = install_archive(&zip_as_rar, &dir.join("mods"), None).unwrap();
+    assert_eq!(installed.folder, "Mod");
+}
+
+const V1_CONFIG: &str = "enabled = true\nname = \"Song Pack\"\nversion = \"1.0\"\ninclude = [\".\"]\n\n[settings]\nlanguage = \"jp\"\n";
+const V2_CONFIG: &str = "enabled = true\nname = \"Song Pack Deluxe\"\nversion = \"2.0\"\ninclude = [\".\", \"extra\"]\n\n[settings]\nlanguage = \"en\"\n";
+
+// Version 1 of a mod installed and then turned off and changed by the user, and an archive of version 2.
+fn installed_v1(dir: &Path) -> (PathBuf, PathBuf) {
+    let mods = dir.join("mods");
+    let v1 = zip(dir, "v1.zip", &[("Song Pack/config.toml", V1_CONFIG), ("Song Pack/rom/old.farc", "")]);
+    install_archive(&v1, &mods, None).unwrap();
+    let config = mods.join("Song Pack/config.toml");
+    fs::write(&config, V1_CONFIG.replace("enabled = true", "enabled = false")).unwrap();
+    fs::write(mods.join("Song Pack/notes.txt"), "mine").unwrap();
+
+    let v2 = zip(dir, "v2.zip", &[("Song Pack/config.toml", V2_CONFIG), ("Song Pack/rom/new.farc", "")]);
+    (mods, v2)
+}
+
+fn config(path: &Path) -> ModConfig {
+    toml::from_str(&fs::read_to_string(path.join("config.toml")).unwrap()).unwrap()
+}
+
+#[test]
+fn installing_over_a_mod_asks_first() {
+    let dir = TestDir::new("ask");
+    let (mods, v2) = installed_v1(&dir);
+
+    let err = install_archive(&v2, &mods, None).unwrap_err();
+
+    assert!(matches!(err.0, InstallErrorType::AlreadyInstalled(folder) if folder == "Song Pack"));
+    assert!(mods.join("Song Pack/rom/old.farc").is_file());
+}
+
+#[test]
+fn upgrade_keeps_user_settings_and_takes_new_metadata() {
+    let dir = TestDir::new("upgrade");
+    let (mods, v2) = installed_v1(&dir);
+
+    install_archive(&v2, &mods, Some(ConflictAction::Upgrade)).unwrap();
+
+    let folder = mods.join("Song Pack");
+    let config = config(&folder);
+    assert!(!config.enabled);
+    assert_eq!(config.name.as_deref(), Some("Song Pack Deluxe"));
+    assert_eq!(config.version.as_deref(), Some("2.0"));
+    assert_eq!(config.include, Some(vec![String::from("."), String::from("extra")]));
+    let raw: toml::Value = toml::from_str(&fs::read_to_string(folder.join("config.toml")).unwrap()).unwrap();
+    assert_eq!(raw["settings"]["language"].as_str(), Some("jp"));
+    //The files are the new version's
+    assert!(folder.join("rom/new.farc").is_file());
+    assert!(!folder.join("rom/old.farc").exists());
+    //Nothing else ends up in the mod, like a backup of the config
+    let mut names: Vec<String> = fs::read_dir(&folder).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect();
+    names.sort();
+    assert_eq!(names, vec!["config.toml", "rom"]);
+}
+
+#[test]
+fn keep_both_installs_next_to_the_old_one() {
+    let dir = TestDir::new("keepboth");
+    let (mods, v2) = installed_v1(&dir);
+
+    let installed = install_archive(&v2, &mods, Some(ConflictAction::KeepBoth)).unwrap();
+    assert_eq!(installed.folder, "Song Pack (2)");
+    let again = install_archive(&v2, &mods, Some(ConflictAction::KeepBoth)).unwrap();
+    assert_eq!(again.folder, "Song Pack (3)");
+
+    assert!(!config(&mods.join("Song Pack")).enabled);
+    assert!(mods.join("Song Pack/rom/old.farc").is_file());
+    assert!(config(&mods.join("Song Pack (2)")).enabled);
+    assert!(mods.join("Song Pack (2)/rom/new.farc").is_file());
+}
+
+#[test]
+fn overwrite_replaces_the_whole_folder() {
+    let dir = TestDir::new("overwrite");
+    let (mods, v2) = installed_v1(&dir);
+
+    install_archive(&v2, &mods, Some(ConflictAction::Overwrite)).unwrap();
+
+    let folder = mods.join("Song Pack");
+    assert_eq!(fs::read_to_string(folder.join("config.toml")).unwrap(), V2_CONFIG);
+    assert!(folder.join("rom/new.farc").is_file());
+    assert!(!folder.join("rom/old.farc").exists());
+    assert!(!folder.join("notes.txt").exists());
+    //Nothing left over from the install
+    let names: Vec<String> = fs::read_dir(&mods).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect();
+    assert_eq!(names, vec!["Song Pack"]);
+    assert!(!dir.join(".m4-staging").exists());
+}
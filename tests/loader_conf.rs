@@ -0,0 +1,39 @@
+use m4::loader_conf::LoaderConfig;
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+fn with_priority(priority: &[&str]) -> LoaderConfig {
+    LoaderConfig { priority: names(priority), ..Default::default() }
+}
+
+#[test]
+fn installed_mods_follow_the_priority_list() {
+    let config = with_priority(&["C", "A", "B"]);
+
+    assert_eq!(config.ordered_mods(&names(&["A", "B", "C"])), names(&["C", "A", "B"]));
+}
+
+#[test]
+fn mods_missing_from_priority_go_last_sorted_by_name() {
+    let config = with_priority(&["B"]);
+
+    //The same order whatever order the folders were found in
+    assert_eq!(config.ordered_mods(&names(&["D", "B", "A", "C"])), names(&["B", "A", "C", "D"]));
+    assert_eq!(config.ordered_mods(&names(&["C", "A", "D", "B"])), names(&["B", "A", "C", "D"]));
+}
+
+#[test]
+fn priority_entries_of_removed_mods_and_duplicates_are_skipped() {
+    let config = with_priority(&["Gone", "B", "A", "B"]);
+
+    assert_eq!(config.ordered_mods(&names(&["A", "B"])), names(&["B", "A"]));
+}
+
+#[test]
+fn saving_an_order_keeps_entries_of_removed_mods_at_the_end() {
+    let config = with_priority(&["Gone", "B", "A"]);
+
+    assert_eq!(config.priority_for(&names(&["A", "B", "New"])), names(&["A", "B", "New", "Gone"]));
+}
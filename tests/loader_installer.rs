@@ -0,0 +1,89 @@
+mod common;
+
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use m4::loader_conf::LoaderConfig;
+use m4::loader_installer::{install_loader, installed_version, version_from_name, LoaderInstallErrorType};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+use common::TestDir;
+
+// A test directory with an empty game folder in it.
+fn test_dir(name: &str) -> TestDir {
+    let dir = TestDir::new(name);
+    fs::create_dir(dir.join("game")).unwrap();
+    dir
+}
+
+fn release(path: &Path, files: &[(&str, &str)]) {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    for (name, contents) in files {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+const RELEASE_CONFIG: &str = "enabled = true\nconsole = false\nmods = \"mods\"\n";
+
+#[test]
+fn installs_into_an_empty_game_dir() {
+    let dir = test_dir("fresh");
+    let game = dir.join("game");
+    let archive = dir.join("DivaModLoader-v1.0.2.zip");
+    release(&archive, &[("DivaModLoader/dinput8.dll", "dll v1"), ("DivaModLoader/config.toml", RELEASE_CONFIG), ("README.md", "")]);
+
+    let installed = install_loader(&archive, &game, None).unwrap();
+
+    assert_eq!(installed.version.as_deref(), Some("1.0.2"));
+    assert!(!installed.kept_config);
+    assert_eq!(fs::read_to_string(game.join("dinput8.dll")).unwrap(), "dll v1");
+    assert_eq!(fs::read_to_string(game.join("config.toml")).unwrap(), RELEASE_CONFIG);
+    assert_eq!(installed_version(&game).as_deref(), Some("1.0.2"));
+    assert!(!game.join(".m4-loader-install").exists());
+    assert!(!game.join("README.md").exists());
+}
+
+#[test]
+fn keeps_the_existing_config() {
+    let dir = test_dir("update");
+    let game = dir.join("game");
+    fs::write(game.join("dinput8.dll"), "dll v1").unwrap();
+    fs::write(game.join("config.toml"), "# mine\nenabled = false\npriority = [\"B\", \"A\"]\n").unwrap();
+    let archive = dir.join("DivaModLoader.zip");
+    release(&archive, &[("dinput8.dll", "dll v2"), ("config.toml", "enabled = true\npriority = []\nnew_setting = 3\n")]);
+
+    let installed = install_loader(&archive, &game, Some(String::from("1.1.0"))).unwrap();
+
+    assert!(installed.kept_config);
+    assert_eq!(fs::read_to_string(game.join("dinput8.dll")).unwrap(), "dll v2");
+    assert_eq!(fs::read_to_string(game.join("config.toml")).unwrap(), "# mine\nenabled = false\npriority = [\"B\", \"A\"]\nnew_setting = 3\n");
+    let config = LoaderConfig::load(&game).unwrap();
+    assert_eq!(config.priority, vec!["B", "A"]);
+    assert_eq!(installed_version(&game).as_deref(), Some("1.1.0"));
+}
+
+#[test]
+fn refuses_archives_without_the_loader() {
+    let dir = test_dir("nodll");
+    let game = dir.join("game");
+    let archive = dir.join("SomeMod.zip");
+    release(&archive, &[("SomeMod/config.toml", "enabled = true\n")]);
+
+    let err = install_loader(&archive, &game, None).unwrap_err();
+
+    assert!(matches!(err.0, LoaderInstallErrorType::NoLoaderDll));
+    assert_eq!(fs::read_dir(&game).unwrap().count(), 0);
+    assert_eq!(installed_version(&game), None);
+}
+
+#[test]
+fn version_comes_from_the_archive_name() {
+    assert_eq!(version_from_name("DivaModLoader-v1.0.2.zip").as_deref(), Some("1.0.2"));
+    assert_eq!(version_from_name("DivaModLoader_1.3.7z").as_deref(), Some("1.3"));
+    assert_eq!(version_from_name("v2.0.1").as_deref(), Some("2.0.1"));
+    assert_eq!(version_from_name("DivaModLoader.zip"), None);
+    assert_eq!(version_from_name("dinput8.zip"), None);
+}
@@ -0,0 +1,186 @@
+mod common;
+
+use std::cmp::Ordering;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::thread;
+use iced::futures::executor::block_on;
+use m4::loader_installer::installed_version;
+use m4::loader_update::{check_for_update, compare_versions, download, fetch_latest, install_with_rollback, update, update_loader, Release, UpdateCheck, UpdateErrorType};
+use url::Url;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+use common::TestDir;
+
+// A test directory with an empty game folder in it.
+fn test_dir(name: &str) -> TestDir {
+    let dir = TestDir::new(name);
+    fs::create_dir(dir.join("game")).unwrap();
+    dir
+}
+
+fn release_zip(path: &Path, dll: &str) {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    zip.start_file("dinput8.dll", FileOptions::default()).unwrap();
+    zip.write_all(dll.as_bytes()).unwrap();
+    zip.start_file("config.toml", FileOptions::default()).unwrap();
+    zip.write_all(b"enabled = true\nmods = \"mods\"\n").unwrap();
+    zip.finish().unwrap();
+}
+
+fn file_url(path: &Path) -> String {
+    Url::from_file_path(path).unwrap().to_string()
+}
+
+fn feed(releases: &[(&str, bool, &str)]) -> String {
+    let releases: Vec<String> = releases.iter().map(|(tag, prerelease, url)| format!(
+        r#"{{"tag_name": "{}", "draft": false, "prerelease": {}, "assets": [{{"name": "DivaModLoader.zip", "browser_download_url": "{}"}}]}}"#,
+        tag, prerelease, url)).collect();
+    format!("[{}]", releases.join(", "))
+}
+
+#[test]
+fn versions_compare_by_number() {
+    assert_eq!(compare_versions("1.0.9", "1.0.10"), Ordering::Less);
+    assert_eq!(compare_versions("v1.1", "1.1.0"), Ordering::Equal);
+    assert_eq!(compare_versions("2.0", "1.9.9"), Ordering::Greater);
+}
+
+#[test]
+fn latest_skips_pre_releases() {
+    let dir = test_dir("feed");
+    let feed_path = dir.join("releases.json");
+    fs::write(&feed_path, feed(&[("v1.2.0-beta", true, "file:///beta.zip"), ("v1.1.0", false, "file:///stable.zip")])).unwrap();
+
+    let latest = fetch_latest(&file_url(&feed_path)).unwrap();
+
+    assert_eq!(latest.version, "1.1.0");
+    assert_eq!(latest.download_url, "file:///stable.zip");
+
+    //A single release, like /releases/latest
+    fs::write(&feed_path, r#"{"tag_name": "v2.0", "assets": [{"name": "DivaModLoader.zip", "browser_download_url": "file:///x.zip"}]}"#).unwrap();
+    assert_eq!(fetch_latest(&file_url(&feed_path)).unwrap().version, "2.0");
+
+    fs::write(&feed_path, "[]").unwrap();
+    assert!(matches!(fetch_latest(&file_url(&feed_path)).unwrap_err().0, UpdateErrorType::NoRelease));
+}
+
+#[test]
+fn updates_from_a_local_mirror() {
+    let dir = test_dir("mirror");
+    let game = dir.join("game");
+    let archive = dir.join("DivaModLoader.zip");
+    release_zip(&archive, "dll v2");
+    let feed_path = dir.join("releases.json");
+    fs::write(&feed_path, feed(&[("v2.0.0", false, &file_url(&archive))])).unwrap();
+
+    let latest = fetch_latest(&file_url(&feed_path)).unwrap();
+    let check = UpdateCheck { installed: installed_version(&game), latest };
+    assert!(check.update_available());
+    //Left over from an update that was cut short
+    fs::write(game.join(".m4-download-DivaModLoader.zip"), "partial").unwrap();
+    update(&game, &check.latest).unwrap();
+
+    assert_eq!(fs::read_to_string(game.join("dinput8.dll")).unwrap(), "dll v2");
+    assert_eq!(installed_version(&game).as_deref(), Some("2.0.0"));
+    //The downloaded archive is cleaned up
+    assert!(!fs::read_dir(&game).unwrap().any(|e| e.unwrap().file_name().to_string_lossy().starts_with(".m4-download")));
+}
+
+#[test]
+fn checks_and_updates_in_the_background() {
+    let dir = test_dir("background");
+    let game = dir.join("game");
+    let archive = dir.join("DivaModLoader.zip");
+    release_zip(&archive, "dll v2");
+    let feed_path = dir.join("releases.json");
+    fs::write(&feed_path, feed(&[("v2.0.0", false, &file_url(&archive))])).unwrap();
+
+    let check = block_on(check_for_update(game.clone(), file_url(&feed_path))).unwrap();
+    assert!(check.update_available());
+    block_on(update_loader(game.clone(), check.latest)).unwrap();
+
+    let check = block_on(check_for_update(game.clone(), file_url(&feed_path))).unwrap();
+    assert_eq!(check.installed.as_deref(), Some("2.0.0"));
+    assert!(!check.update_available());
+}
+
+#[test]
+fn updates_over_http() {
+    let dir = test_dir("http");
+    let game = dir.join("game");
+    let archive = dir.join("DivaModLoader.zip");
+    release_zip(&archive, "dll v3");
+    let zip_data = fs::read(&archive).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let feed_body = feed(&[("v3.0.0", false, &format!("{}/DivaModLoader.zip", base))]).into_bytes();
+    let server = thread::spawn(move || {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            let body = if request.contains("/releases") { &feed_body } else { &zip_data };
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).unwrap();
+            stream.write_all(body).unwrap();
+        }
+    });
+
+    let latest = fetch_latest(&format!("{}/releases", base)).unwrap();
+    update(&game, &latest).unwrap();
+    server.join().unwrap();
+
+    assert_eq!(fs::read_to_string(game.join("dinput8.dll")).unwrap(), "dll v3");
+    assert_eq!(installed_version(&game).as_deref(), Some("3.0.0"));
+}
+
+#[test]
+fn cut_short_downloads_leave_nothing_behind() {
+    let dir = test_dir("cut-short");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let release = Release {
+        version: String::from("2.0.0"),
+        tag: String::from("v2.0.0"),
+        archive_name: String::from("DivaModLoader.zip"),
+        download_url: format!("http://{}/DivaModLoader.zip", listener.local_addr().unwrap()),
+    };
+    //Promises more than it sends, then hangs up
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request).unwrap();
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 4096\r\nConnection: close\r\n\r\nPK\x03\x04").unwrap();
+    });
+
+    let err = download(&release, &dir).unwrap_err();
+    server.join().unwrap();
+
+    assert!(matches!(err.0, UpdateErrorType::Download(_)), "{}", err);
+    assert!(!fs::read_dir(&*dir).unwrap().any(|e| e.unwrap().file_name().to_string_lossy().starts_with(".m4-download")));
+}
+
+#[test]
+fn failed_install_puts_the_old_loader_back() {
+    let dir = test_dir("rollback");
+    let game = dir.join("game");
+    fs::write(game.join("dinput8.dll"), "dll v1").unwrap();
+    fs::write(game.join("config.toml"), "priority = [\"A\"]\n").unwrap();
+    fs::write(game.join("m4_loader.toml"), "version = \"1.0.0\"\n").unwrap();
+    //The config gets updated, then the dll can't be written
+    fs::create_dir(game.join("dinput8.dll.bak")).unwrap();
+    let archive = dir.join("DivaModLoader.zip");
+    release_zip(&archive, "dll v2");
+
+    let err = install_with_rollback(&archive, &game, Some(String::from("2.0.0"))).unwrap_err();
+
+    assert!(matches!(err.0, UpdateErrorType::Install(_)), "{}", err);
+    assert_eq!(fs::read_to_string(game.join("dinput8.dll")).unwrap(), "dll v1");
+    assert_eq!(fs::read_to_string(game.join("config.toml")).unwrap(), "priority = [\"A\"]\n");
+    assert_eq!(installed_version(&game).as_deref(), Some("1.0.0"));
+    //Putting it back doesn't replace the backup with the half installed config
+    assert_eq!(fs::read_to_string(game.join("config.toml.bak")).unwrap(), "priority = [\"A\"]\n");
+}
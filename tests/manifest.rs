@@ -0,0 +1,128 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use m4::loader_conf::LoaderConfig;
+use m4::manifest::{EntryStatus, Manifest, ManifestFormat};
+use m4::mod_catalog::{ModCatalog, ModEntry};
+use m4::mod_conf::ModConfig;
+use common::TestDir;
+
+fn installed(folder: &str, name: &str, version: &str, enabled: bool) -> ModEntry {
+    ModEntry {
+        path: PathBuf::from("/games/mm/mods").join(folder),
+        config: ModConfig {
+            enabled,
+            name: Some(name.to_string()),
+            version: Some(version.to_string()),
+            ..Default::default()
+        },
+        files: Vec::new(),
+        pvs: Vec::new(),
+        archives: Vec::new(),
+    }
+}
+
+fn catalog() -> ModCatalog {
+    ModCatalog {
+        mods_dir: PathBuf::from("/games/mm/mods"),
+        mods: vec![
+            installed("BetterSelector", "Better Module Selector", "1.2.0", true),
+            installed("HDUI", "HD UI", "2.0", false),
+            installed("Charts", "Chart Pack", "1.0", true),
+        ],
+        errors: Vec::new(),
+    }
+}
+
+#[test]
+fn export_follows_the_load_order() {
+    let loader = LoaderConfig {
+        priority: vec![String::from("HDUI"), String::from("Charts")],
+        ..Default::default()
+    };
+
+    let manifest = Manifest::export(&loader, &catalog());
+
+    let order: Vec<(&str, usize)> = manifest.mods.iter().map(|m| (m.folder.as_str(), m.priority)).collect();
+    assert_eq!(order, vec![("HDUI", 1), ("Charts", 2), ("BetterSelector", 3)]);
+}
+
+#[test]
+fn toml_and_json_round_trip() {
+    let manifest = Manifest::export(&LoaderConfig::default(), &catalog());
+
+    for format in [ManifestFormat::Toml, ManifestFormat::Json] {
+        let text = manifest.to_string(format).unwrap();
+        let read = Manifest::from_str(&text, format).unwrap();
+        assert_eq!(read.mods.len(), 3);
+        assert_eq!(read.mods[0].name.as_deref(), Some("Better Module Selector"));
+        assert_eq!(read.mods[2].version.as_deref(), Some("2.0"));
+        assert!(!read.mods[2].enabled);
+    }
+}
+
+#[test]
+fn diff_finds_missing_and_different_versions() {
+    let manifest = Manifest::from_str(r#"
+manifest_version = 1
+
+[[mods]]
+folder = "Charts"
+name = "Chart Pack"
+version = "1.1"
+enabled = false
+priority = 1
+
+[[mods]]
+folder = "BMS"
+name = "better module selector"
+version = "1.2.0"
+enabled = true
+priority = 2
+
+[[mods]]
+folder = "NewSongs"
+name = "New Songs"
+enabled = true
+priority = 3
+"#, ManifestFormat::Toml).unwrap();
+
+    let diff = manifest.diff(&catalog());
+
+    assert_eq!(diff.entries[0].status, EntryStatus::DifferentVersion(Some(String::from("1.0"))));
+    //Different folder, same mod name
+    assert_eq!(diff.entries[1].status, EntryStatus::Installed);
+    assert_eq!(diff.entries[1].local_folder.as_deref(), Some("BetterSelector"));
+    assert_eq!(diff.entries[2].status, EntryStatus::Missing);
+
+    let profile = diff.to_profile("shared");
+    assert_eq!(profile.priority, vec![String::from("Charts"), String::from("BetterSelector")]);
+    assert_eq!(profile.mods.get("Charts"), Some(&false));
+    assert!(!profile.mods.contains_key("NewSongs"));
+}
+
+#[test]
+fn newer_manifests_are_refused() {
+    assert!(Manifest::from_str("manifest_version = 99\n", ManifestFormat::Toml).is_err());
+}
+
+#[test]
+fn exporting_picks_up_changes_made_after_the_scan() {
+    let dir = TestDir::new("current");
+    for folder in ["A", "B"] {
+        fs::create_dir_all(dir.join("mods").join(folder)).unwrap();
+        fs::write(dir.join("mods").join(folder).join("config.toml"), "enabled = true\n").unwrap();
+    }
+    fs::write(dir.join("config.toml"), "priority = [\"A\", \"B\"]\n").unwrap();
+    let catalog = ModCatalog::scan(&dir.join("mods")).unwrap();
+
+    //B turned off and moved to the top on Home
+    ModConfig::write_enabled(&dir.join("mods/B/config.toml"), false).unwrap();
+    LoaderConfig { priority: vec![String::from("B"), String::from("A")], ..Default::default() }.save(&dir).unwrap();
+
+    let manifest = Manifest::export_current(&dir, &catalog).unwrap();
+
+    let mods: Vec<(&str, bool, usize)> = manifest.mods.iter().map(|m| (m.folder.as_str(), m.enabled, m.priority)).collect();
+    assert_eq!(mods, vec![("B", false, 1), ("A", true, 2)]);
+}
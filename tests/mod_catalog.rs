@@ -0,0 +1,53 @@
+mod common;
+
+use std::fs;
+use iced::futures::executor::block_on;
+use m4::mod_catalog::{ModCatalog, ModLoadErrorType};
+use common::TestDir;
+
+#[test]
+fn broken_mods_are_reported_and_the_rest_still_load() {
+    let dir = TestDir::new("broken");
+    for (folder, config) in [("Good", Some("enabled = true\nname = \"Good Mod\"\n")), ("Bad", Some("enabled = tru\n")), ("Empty", None)] {
+        fs::create_dir(dir.join(folder)).unwrap();
+        if let Some(config) = config {
+            fs::write(dir.join(folder).join("config.toml"), config).unwrap();
+        }
+    }
+    //Files next to the mods and hidden folders aren't mods
+    fs::write(dir.join("readme.txt"), "").unwrap();
+    fs::create_dir(dir.join(".hidden")).unwrap();
+
+    let catalog = ModCatalog::scan(&dir).unwrap();
+
+    let names: Vec<String> = catalog.mods.iter().map(|m| m.display_name()).collect();
+    assert_eq!(names, vec!["Good Mod"]);
+    let errors: Vec<(String, &ModLoadErrorType)> = catalog.errors.iter().map(|e| (e.folder_name(), &e.error)).collect();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].0, "Bad");
+    assert!(matches!(errors[0].1, ModLoadErrorType::BadToml(_)));
+    assert_eq!(errors[1].0, "Empty");
+    assert!(matches!(errors[1].1, ModLoadErrorType::MissingConfig));
+}
+
+#[test]
+fn a_missing_mods_folder_is_an_empty_catalog() {
+    let dir = TestDir::new("missing");
+
+    let catalog = ModCatalog::scan(&dir.join("mods")).unwrap();
+
+    assert!(catalog.mods.is_empty());
+    assert!(catalog.errors.is_empty());
+}
+
+#[test]
+fn load_gives_the_same_catalog_as_scan() {
+    let dir = TestDir::new("load");
+    fs::create_dir(dir.join("A")).unwrap();
+    fs::write(dir.join("A").join("config.toml"), "enabled = true\nname = \"A\"\n").unwrap();
+
+    let catalog = block_on(ModCatalog::load(dir.to_path_buf())).unwrap();
+
+    assert_eq!(catalog.mods.len(), 1);
+    assert_eq!(catalog.mods[0].display_name(), "A");
+}
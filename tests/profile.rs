@@ -0,0 +1,107 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use m4::loader_conf::LoaderConfig;
+use m4::mod_catalog::ModCatalog;
+use m4::mod_conf::ModConfig;
+use m4::profile::Profile;
+use common::TestDir;
+
+// A game directory with mods A (on) and B (off), loaded in that order.
+fn game(dir: &Path) -> ModCatalog {
+    for (folder, enabled) in [("A", true), ("B", false)] {
+        let mod_dir = dir.join("mods").join(folder);
+        fs::create_dir_all(&mod_dir).unwrap();
+        fs::write(mod_dir.join("config.toml"), format!("enabled = {}\n", enabled)).unwrap();
+    }
+    fs::write(dir.join("config.toml"), "enabled = true\npriority = [\"A\", \"B\"]\n").unwrap();
+    ModCatalog::scan(&dir.join("mods")).unwrap()
+}
+
+fn enabled(dir: &Path, folder: &str) -> bool {
+    let config: ModConfig = toml::from_str(&fs::read_to_string(dir.join("mods").join(folder).join("config.toml")).unwrap()).unwrap();
+    config.enabled
+}
+
+fn profile(mods: &[(&str, bool)], priority: &[&str]) -> Profile {
+    Profile {
+        name: String::from("Test"),
+        priority: priority.iter().map(|p| p.to_string()).collect(),
+        mods: mods.iter().map(|(f, e)| (f.to_string(), *e)).collect::<BTreeMap<String, bool>>(),
+        file: None,
+    }
+}
+
+#[test]
+fn applying_after_a_toggle_uses_what_is_on_disk() {
+    let dir = TestDir::new("toggled");
+    let catalog = game(&dir);
+    //Turned off on Home after the catalog was scanned
+    ModConfig::write_enabled(&dir.join("mods/A/config.toml"), false).unwrap();
+
+    let report = profile(&[("A", true), ("B", false)], &["A", "B"]).apply(&dir, &catalog).unwrap();
+
+    assert_eq!(report.changed, vec!["A"]);
+    assert!(enabled(&dir, "A"));
+    assert!(!enabled(&dir, "B"));
+}
+
+#[test]
+fn capturing_after_a_toggle_and_reorder_saves_the_current_setup() {
+    let dir = TestDir::new("capture");
+    let catalog = game(&dir);
+    ModConfig::write_enabled(&dir.join("mods/B/config.toml"), true).unwrap();
+    let mut loader = LoaderConfig::load(&dir).unwrap();
+    loader.priority = vec![String::from("B"), String::from("A")];
+    loader.save(&dir).unwrap();
+
+    let captured = Profile::capture(" Mine ", &dir, &catalog).unwrap();
+
+    assert_eq!(captured.name, "Mine");
+    assert_eq!(captured.priority, vec!["B", "A"]);
+    assert_eq!(captured.mods.get("A"), Some(&true));
+    assert_eq!(captured.mods.get("B"), Some(&true));
+}
+
+#[test]
+fn applying_sets_the_load_order_and_reports_missing_mods() {
+    let dir = TestDir::new("order");
+    let catalog = game(&dir);
+
+    let report = profile(&[("A", false), ("B", true), ("Gone", true)], &["B", "Gone", "A"]).apply(&dir, &catalog).unwrap();
+
+    assert_eq!(report.changed, vec!["A", "B"]);
+    assert_eq!(report.missing, vec!["Gone"]);
+    assert_eq!(LoaderConfig::load(&dir).unwrap().priority, vec!["B", "Gone", "A"]);
+}
+
+#[test]
+fn names_that_share_a_file_name_do_not_overwrite_each_other() {
+    let dir = TestDir::new("collide");
+    let first = Profile { name: String::from("A/B"), ..profile(&[("A", true)], &["A"]) };
+    let second = Profile { name: String::from("A_B"), ..profile(&[("A", false)], &["A"]) };
+
+    let path = first.save(&dir).unwrap();
+    assert!(second.save(&dir).is_err());
+    assert_eq!(Profile::load(&path).unwrap().name, "A/B");
+
+    //Saving the same name again still replaces it
+    first.save(&dir).unwrap();
+}
+
+#[test]
+fn delete_removes_the_file_it_was_read_from() {
+    let dir = TestDir::new("delete");
+    //Written by hand, the file name isn't what the name sanitizes to
+    fs::write(dir.join("mine.toml"), "name = \"Tournament\"\n").unwrap();
+    fs::write(dir.join("Tournament.toml"), "name = \"Tournament copy\"\n").unwrap();
+
+    let (profiles, _) = Profile::list(&dir);
+    let profile = profiles.iter().find(|p| p.name == "Tournament").unwrap();
+    profile.delete().unwrap();
+
+    assert!(!dir.join("mine.toml").exists());
+    assert!(dir.join("Tournament.toml").exists());
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use m4::file_index::ModFile;
+use m4::mod_catalog::{ModCatalog, ModEntry};
+use m4::mod_conf::ModConfig;
+use m4::pv_db;
+
+const PV_DB: &str = "\u{feff}# song pack\r
+pv_801.bpm=150\r
+pv_801.song_name=ハロー\r
+pv_801.song_name_en=Hello\r
+pv_802.song_name=Only Japanese\r
+pv_802.difficulty.hard.0.level=PV_LV_07_5\r
+pv_list.length=2\r
+pv_abc.song_name=Broken\r
+not a pv line\r
+";
+
+fn installed(folder: &str, enabled: bool, pv_db: &str) -> ModEntry {
+    ModEntry {
+        path: PathBuf::from("/games/mm/mods").join(folder),
+        config: ModConfig { enabled, ..Default::default() },
+        files: Vec::new(),
+        pvs: pv_db::parse(pv_db),
+        archives: Vec::new(),
+    }
+}
+
+#[test]
+fn parses_song_ids_and_names() {
+    let entries = pv_db::parse(PV_DB);
+
+    let ids: Vec<u32> = entries.iter().map(|e| e.id).collect();
+    assert_eq!(ids, vec![801, 802]);
+    assert_eq!(entries[0].song_name.as_deref(), Some("ハロー"));
+    assert_eq!(entries[0].name(), "Hello");
+    assert_eq!(entries[1].name(), "Only Japanese");
+}
+
+#[test]
+fn only_rom_pv_db_files_are_read() {
+    let file = |path: &str| ModFile { path: path.to_string(), source: PathBuf::from(path) };
+
+    assert!(pv_db::is_pv_db(&file("rom/mod_pv_db.txt")));
+    assert!(pv_db::is_pv_db(&file("ROM_steam/Mod_PV_DB.txt")));
+    assert!(!pv_db::is_pv_db(&file("rom/2d/mod_pv_db.txt")));
+    assert!(!pv_db::is_pv_db(&file("rom/pv_db.txt")));
+}
+
+#[test]
+fn collisions_between_enabled_mods() {
+    let catalog = ModCatalog {
+        mods: vec![
+            installed("A", true, "pv_801.song_name_en=Song A\npv_900.song_name=Solo\n"),
+            installed("B", true, "pv_801.song_name=Song B\n"),
+            installed("C", false, "pv_900.song_name=Hidden\n"),
+        ],
+        ..Default::default()
+    };
+    let order: Vec<String> = vec!["B".into(), "A".into(), "C".into()];
+
+    let collisions = pv_db::collisions(&order, &catalog);
+
+    assert_eq!(collisions.len(), 1);
+    assert_eq!(collisions[0].id, 801);
+    let songs: Vec<(&str, &str)> = collisions[0].songs.iter().map(|s| (s.folder.as_str(), s.name.as_str())).collect();
+    assert_eq!(songs, vec![("B", "Song B"), ("A", "Song A")]);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use iced::futures::executor::block_on;
+use m4::m4_settings::{LoadErrorType, M4Settings, SETTINGS_VERSION};
+use common::TestDir;
+
+fn settings_file(dir: &Path, contents: &str) -> PathBuf {
+    let path = dir.join("settings.toml");
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn unversioned_settings_are_upgraded_with_a_backup() {
+    let dir = TestDir::new("v0");
+    let path = settings_file(&dir, "game_dir = \"/games/mm\"\n");
+
+    let settings = block_on(M4Settings::load(path.clone())).unwrap();
+
+    assert_eq!(settings.game_dir, "/games/mm");
+    assert_eq!(settings.schema_version, SETTINGS_VERSION);
+    assert_eq!(fs::read_to_string(path.with_file_name("settings.toml.v0.bak")).unwrap(), "game_dir = \"/games/mm\"\n");
+    let upgraded: toml::Value = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(upgraded["schema_version"].as_integer(), Some(SETTINGS_VERSION as i64));
+}
+
+#[test]
+fn current_settings_are_left_alone() {
+    let contents = format!("schema_version = {}\ngame_dir = \"/games/mm\"\n", SETTINGS_VERSION);
+    let dir = TestDir::new("current");
+    let path = settings_file(&dir, &contents);
+
+    let settings = block_on(M4Settings::load(path.clone())).unwrap();
+
+    assert_eq!(settings.game_dir, "/games/mm");
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+}
+
+#[test]
+fn future_settings_are_reported_and_not_touched() {
+    let contents = format!("schema_version = {}\ngame_dir = \"/games/mm\"\nnew_thing = 1\n", SETTINGS_VERSION + 1);
+    let dir = TestDir::new("future");
+    let path = settings_file(&dir, &contents);
+
+    let err = block_on(M4Settings::load(path.clone())).unwrap_err();
+
+    assert!(matches!(err.kind(), LoadErrorType::UnsupportedVersion(v) if *v == SETTINGS_VERSION as i64 + 1));
+    assert_eq!(err.path(), path);
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+}
+
+#[test]
+fn parse_errors_count_lines_and_columns_from_one() {
+    let dir = TestDir::new("invalid");
+    let path = settings_file(&dir, "schema_version = 1\ngame_dir = /games/mm\n");
+
+    let err = block_on(M4Settings::load(path.clone())).unwrap_err();
+
+    match err.kind() {
+        LoadErrorType::ParseError { message, line, column } => {
+            assert_eq!(*line, Some(2));
+            assert_eq!(*column, Some(12));
+            //The position is shown once, from line and column
+            assert!(!message.contains(" at line "), "{}", message);
+        }
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+    assert!(err.to_string().contains("line 2, column 12"), "{}", err);
+    assert_eq!(err.path(), path);
+}
+
+#[test]
+fn a_missing_settings_file_is_not_found() {
+    let dir = TestDir::new("missing");
+
+    let err = block_on(M4Settings::load(dir.join("settings.toml"))).unwrap_err();
+
+    assert!(matches!(err.kind(), LoadErrorType::FileNotFound));
+}
+
+#[test]
+fn huge_schema_versions_do_not_wrap_around() {
+    //4294967297 would be 1 as a truncated u32
+    let contents = "schema_version = 4294967297\ngame_dir = \"/games/mm\"\n";
+    let dir = TestDir::new("huge");
+    let path = settings_file(&dir, contents);
+
+    let err = block_on(M4Settings::load(path.clone())).unwrap_err();
+
+    assert!(matches!(err.kind(), LoadErrorType::UnsupportedVersion(4294967297)));
+    assert!(err.to_string().contains("schema version 4294967297"), "{}", err);
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+}
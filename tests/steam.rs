@@ -0,0 +1,133 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use m4::steam::{app_install_dir, library_folders, Vdf, MEGAMIX_APP_ID};
+use common::TestDir;
+
+fn str_at<'a>(vdf: &'a Vdf, keys: &[&str]) -> Option<&'a str> {
+    keys.iter().try_fold(vdf, |v, k| v.get(k))?.as_str()
+}
+
+#[test]
+fn parses_nested_objects_with_case_insensitive_keys() {
+    let vdf = Vdf::parse("\"AppState\"\n{\n\t\"appid\"\t\t\"1761390\"\n\t\"UserConfig\"\n\t{\n\t\t\"language\"\t\t\"english\"\n\t}\n}\n").unwrap();
+
+    assert_eq!(str_at(&vdf, &["appstate", "AppID"]), Some("1761390"));
+    assert_eq!(str_at(&vdf, &["AppState", "UserConfig", "language"]), Some("english"));
+    assert_eq!(vdf.get("AppState").unwrap().children().len(), 2);
+}
+
+#[test]
+fn handles_escapes_and_comments() {
+    let source = r#"
+        // Written by Steam
+        "root"
+        {
+            "quoted"    "say \"hi\""   // trailing comment
+            "windows"   "D:\\Games\\Steam"
+            "tabbed"    "a\tb"
+            unquoted    value
+        }
+    "#;
+    let vdf = Vdf::parse(source).unwrap();
+
+    assert_eq!(str_at(&vdf, &["root", "quoted"]), Some("say \"hi\""));
+    assert_eq!(str_at(&vdf, &["root", "windows"]), Some("D:\\Games\\Steam"));
+    assert_eq!(str_at(&vdf, &["root", "tabbed"]), Some("a\tb"));
+    assert_eq!(str_at(&vdf, &["root", "unquoted"]), Some("value"));
+    assert_eq!(vdf.get("root").unwrap().children().len(), 4);
+}
+
+#[test]
+fn unbalanced_braces_are_not_parsed() {
+    assert!(Vdf::parse("\"root\"\n{\n\t\"key\" \"value\"\n").is_none());
+    assert!(Vdf::parse("\"root\" }").is_none());
+}
+
+// A Steam folder and two more library folders, with libraryfolders.vdf made from `vdf`.
+// {lib1} and {lib2} in `vdf` are replaced with the library paths.
+fn steam_with_libraries(dir: &Path, vdf: &str) {
+    let (lib1, lib2) = (dir.join("lib1"), dir.join("lib2"));
+    for folder in [dir.join("steam/steamapps"), lib1.clone(), lib2.clone()] {
+        fs::create_dir_all(folder).unwrap();
+    }
+    let vdf = vdf.replace("{lib1}", &lib1.to_string_lossy()).replace("{lib2}", &lib2.to_string_lossy());
+    fs::write(dir.join("steam/steamapps/libraryfolders.vdf"), vdf).unwrap();
+}
+
+#[test]
+fn reads_the_current_libraryfolders_layout() {
+    let dir = TestDir::new("libraries");
+    steam_with_libraries(&dir, r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"{lib1}"
+		"label"		""
+		"apps"
+		{
+			"1761390"		"12345"
+		}
+	}
+	"1"
+	{
+		"path"		"{lib2}"
+	}
+	"2"
+	{
+		"path"		"/not/mounted"
+	}
+}
+"#);
+
+    let libraries = library_folders(&dir.join("steam"));
+
+    assert_eq!(libraries, vec![dir.join("steam"), dir.join("lib1"), dir.join("lib2")]);
+}
+
+#[test]
+fn reads_the_legacy_libraryfolders_layout() {
+    let dir = TestDir::new("legacy");
+    //The legacy file has other settings mixed in with the numbered libraries
+    steam_with_libraries(&dir, r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1650000000"
+	"ContentStatsID"		"-1234567890"
+	"1"		"{lib1}"
+	"2"		"{lib2}"
+}
+"#);
+
+    let libraries = library_folders(&dir.join("steam"));
+
+    assert_eq!(libraries, vec![dir.join("steam"), dir.join("lib1"), dir.join("lib2")]);
+}
+
+#[test]
+fn a_missing_libraryfolders_file_leaves_just_the_steam_folder() {
+    let dir = TestDir::new("nolibraries");
+    fs::create_dir_all(dir.join("steam/steamapps")).unwrap();
+
+    assert_eq!(library_folders(&dir.join("steam")), vec![dir.join("steam")]);
+}
+
+#[test]
+fn finds_the_install_dir_from_the_app_manifest() {
+    let dir = TestDir::new("manifest");
+    fs::create_dir_all(dir.join("steamapps")).unwrap();
+    fs::write(dir.join("steamapps/appmanifest_1761390.acf"), r#"
+"AppState"
+{
+	"appid"		"1761390"
+	"name"		"Hatsune Miku: Project DIVA Mega Mix+"
+	"installdir"		"Hatsune Miku Project DIVA Mega Mix Plus"
+	"StateFlags"		"4"
+}
+"#).unwrap();
+
+    assert_eq!(app_install_dir(&dir, MEGAMIX_APP_ID), Some(dir.join("steamapps/common/Hatsune Miku Project DIVA Mega Mix Plus")));
+    assert_eq!(app_install_dir(&dir, "480"), None);
+}
@@ -0,0 +1,107 @@
+use m4::mod_conf::ModConfig;
+use m4::toml_patch::{patch_str, set_value};
+use toml_edit::{Array, Value};
+
+// Layout taken from mods published on GameBanana, comments and custom settings included.
+const COMMENTED_CONFIG: &str = r#"# Mod made by someone
+enabled = true # set to false to turn the mod off
+include = ["."]
+
+name = "Better Module Selector"
+description = "Shows every module in the selector"
+version = "1.2.0"
+author = "someone"
+
+# Settings read by the mod's dll
+[settings]
+show_locked = false
+sort_by = "name"
+"#;
+
+const DLL_CONFIG: &str = "enabled=false\r\ninclude=[\".\"]\r\ndll=[\"Mod.dll\"]\r\nname=\"Windows line endings\"\r\n";
+
+const NO_ENABLED_CONFIG: &str = r#"include = ["."]
+name = "Old style mod"
+
+[extra]
+key = 1
+"#;
+
+fn set_enabled(source: &str, enabled: bool) -> String {
+    patch_str(source, |doc| set_value(doc, "enabled", enabled)).unwrap()
+}
+
+#[test]
+fn only_enabled_line_changes() {
+    let patched = set_enabled(COMMENTED_CONFIG, false);
+    assert_eq!(
+        patched,
+        COMMENTED_CONFIG.replace("enabled = true #", "enabled = false #")
+    );
+}
+
+#[test]
+fn round_trip_is_byte_identical() {
+    for source in [COMMENTED_CONFIG, DLL_CONFIG, NO_ENABLED_CONFIG] {
+        assert_eq!(patch_str(source, |_| {}).unwrap(), source);
+    }
+
+    let toggled = set_enabled(&set_enabled(COMMENTED_CONFIG, false), true);
+    assert_eq!(toggled, COMMENTED_CONFIG);
+
+    let toggled = set_enabled(&set_enabled(DLL_CONFIG, true), false);
+    assert_eq!(toggled, DLL_CONFIG);
+}
+
+#[test]
+fn mixed_line_endings_are_kept_per_line() {
+    //Edited on Linux after it was made on Windows
+    let source = "enabled = true\r\nname = \"Mixed\"\n# added later\nversion = \"1.0\"\r\n";
+
+    let patched = set_enabled(source, false);
+
+    assert_eq!(patched, source.replace("enabled = true", "enabled = false"));
+    assert_eq!(patch_str(source, |_| {}).unwrap(), source);
+}
+
+#[test]
+fn byte_order_mark_is_kept() {
+    let source = format!("\u{feff}{}", DLL_CONFIG);
+
+    let patched = set_enabled(&source, true);
+
+    assert_eq!(patched, format!("\u{feff}{}", DLL_CONFIG.replace("enabled=false", "enabled=true")));
+    assert_eq!(patch_str(&source, |_| {}).unwrap(), source);
+}
+
+#[test]
+fn missing_key_is_added_before_tables() {
+    let patched = set_enabled(NO_ENABLED_CONFIG, true);
+    let config: ModConfig = toml::from_str(&patched).unwrap();
+    assert!(config.enabled);
+    assert!(patched.starts_with("include = [\".\"]\nname = \"Old style mod\"\n"));
+    assert!(patched.ends_with("[extra]\nkey = 1\n"));
+}
+
+#[test]
+fn unknown_keys_survive() {
+    let patched = set_enabled(COMMENTED_CONFIG, false);
+    let value: toml::Value = toml::from_str(&patched).unwrap();
+    assert_eq!(value["settings"]["sort_by"].as_str(), Some("name"));
+    assert_eq!(value["settings"]["show_locked"].as_bool(), Some(false));
+}
+
+#[test]
+fn arrays_keep_their_layout() {
+    let source = "priority = [\n    \"A\",\n    \"B\",\n]\n";
+    let patched = patch_str(source, |doc| {
+        let list: Array = ["B", "C", "A"].into_iter().collect();
+        set_value(doc, "priority", Value::Array(list));
+    }).unwrap();
+    assert_eq!(patched, "priority = [\n    \"B\",\n    \"C\",\n    \"A\",\n]\n");
+}
+
+#[test]
+fn invalid_toml_is_an_error() {
+    assert!(patch_str("enabled = ", |_| {}).is_err());
+}